@@ -6,16 +6,24 @@
 ## Contact: shaunrd0@gmail.com  | URL: www.shaunreed.com | GitHub: shaunrd0   ##
 ##############################################################################*/
 
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 pub mod kcli;
 pub mod kfs;
 pub mod kio;
 pub mod kgit;
 pub mod kerror;
+pub mod kmanifest;
+pub mod kignore;
+pub mod kconfig;
+pub mod kplan;
+pub mod kjournal;
+pub mod krepo;
+pub mod ktemplate;
+pub mod ksecret;
+pub mod khook;
 
-use kerror::Error;
+use kerror::{Error, ErrorKind};
 /// Result alias to return result with Error of various types
 pub type Result<T> = std::result::Result<T, kerror::Error>;
 
@@ -29,8 +37,7 @@ macro_rules! err {
   };
 }
 pub (crate) use err;
-use crate::ErrorKind::Other;
-use crate::kot::kfs::get_target_paths;
+use ErrorKind::Other;
 
 // =============================================================================
 // IMPLEMENTATION
@@ -39,120 +46,398 @@ use crate::kot::kfs::get_target_paths;
 // -----------------------------------------------------------------------------
 
 pub fn handle_args(args: &mut kcli::Cli) -> Result<()> {
+  // Recover any installation that was interrupted before it could finish
+  recover_install(args)?;
+
+  match &args.command {
+    Some(kcli::Command::Uninstall { .. }) => return uninstall(args),
+    Some(kcli::Command::Status { .. }) => return status(args),
+    Some(kcli::Command::List { .. }) => return list(args),
+    Some(kcli::Command::Restore { backup, files }) => {
+      return restore(args, backup.as_ref(), files);
+    },
+    Some(kcli::Command::Encrypt { file, output }) => return encrypt(args, file, output.as_ref()),
+    Some(kcli::Command::Update { allow_modified, .. }) => {
+      let allow_modified = *allow_modified;
+      return update(args, allow_modified);
+    },
+    Some(kcli::Command::Adopt { files, commit, .. }) => return adopt(args, files, *commit),
+    None => { }
+  }
+
+  if args.is_repo && args.dry_run {
+    // A dry run never clones; Plan using a previous clone of the repository if there is one
+    let clone_dir = args.clone_dir.as_ref().unwrap();
+    if !clone_dir.is_dir() {
+      println!("Planned actions for {:?} in {:?}:", args.dotfiles.as_ref().unwrap(), args.install_dir);
+      println!("  {:<7} {:?} -> {:?}", "clone", args.dotfiles.as_ref().unwrap(), clone_dir);
+      println!("The repository must be cloned before configurations can be planned");
+      return Ok(());
+    }
+    return install_configs(args, &mut vec![]);
+  }
   if args.is_repo {
     // Attempt to install dotfiles from a dotfiles repository
     // + No specific configuration required on behalf of dotfiles repo
-    kgit::clone(&args.dotfiles.to_str().unwrap(),
-                &args.clone_dir.as_ref().unwrap())?;
+    let clone_dir = args.clone_dir.to_owned().unwrap();
+    if clone_dir.join(".git").exists() {
+      err!(
+        ErrorKind::GitError(format!("Repository is already cloned into {:?}", clone_dir)),
+        "Run kot update to update the clone and install it again".to_owned()
+      );
+    }
+    let is_empty = clone_dir.read_dir().map_or(true, |mut entries| entries.next().is_none());
+    let repo_url = kgit::get_repo_url(args.dotfiles.as_ref().unwrap())?.unwrap();
+    kgit::clone(&repo_url, &clone_dir, args.branch.as_ref(), args.rev.as_ref())?;
+    let result = kgit::get_commit(&clone_dir).and_then(|commit| {
+      args.commit = Some(commit);
+      return install_or_rollback(args);
+    });
+    // The clone is only recorded once an installation succeeds, so remove it to allow installing it again
+    if result.is_err() && is_empty {
+      println!("Removing the clone of {:?} from {:?}", repo_url, clone_dir);
+      std::fs::remove_dir_all(&clone_dir)?;
+    }
+    return result;
   }
-  return match install_configs(args) {
+  return install_or_rollback(args);
+}
+
+/// Installs the dotfiles, reversing every change applied so far if an error is reached
+/// + Packages installed earlier in the same run are also rolled back, and the manifest is restored
+/// + An interrupted run only recovers the package that was being installed, see recover_install()
+fn install_or_rollback(args: &mut kcli::Cli) -> Result<()> {
+  let manifest = kmanifest::Manifest::load()?;
+  let mut installed = vec![];
+  return match install_configs(args, &mut installed) {
     Ok(_) => Ok(()),
     Err(e) => {
-      // If we reach an error, use our backup_dir to restore configs
+      // If we reach an error, use the journal to reverse each change applied so far
       // + Remove configs we applied that weren't previously on the system
-      uninstall_configs(args)?;
+      // + Restore configs we moved to backup_dir
+      if let Some(journal) = kjournal::Journal::load()? {
+        rollback_install(&journal)?;
+        kjournal::Journal::finish()?;
+      }
+      if !installed.is_empty() {
+        println!("Rolling back packages installed before the error:");
+        for journal in installed.iter().rev() {
+          println!("  {}", journal.package.as_ref().unwrap_or(&journal.name));
+          rollback_install(journal)?;
+        }
+        manifest.save()?;
+      }
       Err(e)
     }
   }
 }
 
-/// Creates symbolic links to the configurations we're installing
-pub fn install_configs(args: &mut kcli::Cli) -> Result<()> {
+/// Creates links or copies of the configurations we're installing
+/// + The journal of each package that is installed is added to installed, so it can be rolled back
+pub fn install_configs(args: &mut kcli::Cli, installed: &mut Vec<kjournal::Journal>) -> Result<()> {
   //
   // Find path that points us to the dotfiles we are installing
   let dotfiles = match args.is_repo {
     // If the dotfiles were provided as a system path, use it
-    false => args.dotfiles.to_owned(),
+    false => args.dotfiles.as_ref().unwrap().to_owned(),
     // If the dotfiles to install was a repository, find the path we cloned to
     true => args.clone_dir.as_ref().unwrap().to_path_buf()
   };
 
-  //
-  // Check if there are any existing files in the install directory that are also within the dotfiles to install
+  // Each package is planned, backed up, and recorded separately
+  let repo = krepo::Repo::load(&dotfiles)?;
+  for package in repo.get_packages(&dotfiles, &args.packages)? {
+    installed.extend(install_package(args, &dotfiles, &package)?);
+  }
+  return Ok(());
+}
 
-  // Get the configurations and their target installation paths in a hashmap<config, target_path>
-  // + Using target_path, check for conflicts and prompts user to abort or continue
-  let config_map = kfs::get_target_paths(&args.install_dir, &dotfiles)?;
-  handle_collisions(args, &config_map)?;
+/// Creates links or copies of the configurations within a single package
+/// + Returns the journal of the installation once it is recorded, or None for a dry run
+fn install_package(args: &kcli::Cli, dotfiles: &PathBuf, package: &krepo::Package)
+                   -> Result<Option<kjournal::Journal>> {
+  let backup_dir = args.get_backup_dir(package.name.as_ref())?;
+  let description = match &package.name {
+    Some(name) => format!("package {:?} from {:?}", name, dotfiles),
+    None => format!("{:?}", dotfiles),
+  };
+
+  //
+  // Plan each change to the install directory before modifying anything
+  // + Any existing files in the install directory that are also within the dotfiles to install are conflicts
+  let manifest = kmanifest::Manifest::load()?;
+  let actions = kplan::plan_install(args, &manifest, dotfiles, package, &backup_dir)?;
+  let hooks = match args.no_hooks {
+    true => khook::Hooks::default(),
+    false => package.hooks.to_owned(),
+  };
+  if args.dry_run {
+    println!("Planned actions for {} in {:?}:", description, args.install_dir);
+    if let Some(command) = hooks.get(khook::Hook::PreInstall) {
+      println!("  {:<7} {}: {}", "hook", khook::Hook::PreInstall, command);
+    }
+    for action in actions.iter() {
+      println!("  {}", action);
+    }
+    if let Some(command) = hooks.get(khook::Hook::PostInstall) {
+      println!("  {:<7} {}: {}", "hook", khook::Hook::PostInstall, command);
+    }
+    return Ok(None);
+  }
+  // Using the planned backups, prompt user to abort or continue
+  let conflicts = check_collisions(&actions);
+  if let Some(conflicts) = &conflicts {
+    handle_collisions(args, conflicts, &backup_dir)?;
+  }
+  // Nothing has been changed yet, so a failing pre-install hook leaves nothing to roll back
+  hooks.run(khook::Hook::PreInstall, dotfiles, package, &args.install_dir)?;
+  if conflicts.is_some() {
+    args.create_backup_dir(&backup_dir)?;
+  }
 
   //
   // Install the dotfiles configurations
 
   // At this point there are either no conflicts or the user agreed to them
-  println!("Installing configs:");
-  for (config_path, target_path) in &config_map {
-    println!("  + {:?}", target_path);
-    std::os::unix::fs::symlink(config_path, target_path)
-        .or_else(|err| -> Result<()> {
-          eprintln!("Error: Unable to create symlink {:?} -> {:?} ({:?})",
-                    target_path, config_path, err);
-
-          // Attempt to remove the file or directory first, and then symlink the new config
-          match target_path.is_dir() {
-            true => fs_extra::dir::remove(target_path)
-                .expect(&format!("Error: Unable to remove directory: {:?}", target_path)),
-            false => fs_extra::file::remove(target_path)
-                .expect(&format!("Error: Unable to remove file: {:?}", target_path)),
-          };
-          // Try to symlink the config again, if failure exit with error
-          std::os::unix::fs::symlink(config_path, target_path).or_else(|err| {
-            eprintln!("Error: Unable to symlink config: {:?} -> {:?}",
-                      target_path, config_path);
-            return Err(err);
-          })?;
-
-          return Ok(());
-        })?;
+  println!("Installing {}:", description);
+  let mut journal = kjournal::Journal::new(args, dotfiles, package.name.as_ref(),
+                                           &backup_dir, actions)?;
+  apply_install(&mut journal)?;
+  // The journal is kept until the post-install hook succeeds, so a failing hook rolls back the installation
+  hooks.run(khook::Hook::PostInstall, dotfiles, package, &args.install_dir)?;
+  finish_install(&journal)?;
+  return Ok(Some(journal));
+}
+
+/// Applies each action within the journal that has not been applied yet
+/// + Each step is saved to the journal before it is applied, so an interrupted run can be recovered
+/// + Once every action is applied, the installation is recorded and the journal is removed
+fn apply_install(journal: &mut kjournal::Journal) -> Result<()> {
+  for step in journal.step..journal.actions.len() {
+    journal.begin(step)?;
+    apply_action(&journal.actions[step])?;
   }
+  return journal.begin(journal.actions.len());
+}
+
+/// Records an installation once every action within the journal has been applied, and removes the journal
+fn finish_install(journal: &kjournal::Journal) -> Result<()> {
+  //
+  // Record the installation so it can be inspected or reversed later
+  kmanifest::Manifest::record(journal.to_install())?;
+  return kjournal::Journal::finish();
+}
 
+/// Applies a single planned action to the filesystem
+/// + Actions that were already applied are skipped, so an interrupted action can be applied again
+fn apply_action(action: &kplan::Action) -> Result<()> {
+  match action {
+    kplan::Action::Remove { config, target, mode, backup } => {
+      if kfs::is_installed(config, target, *mode) {
+        println!("  - {:?}", target);
+        kfs::remove_config(target)?;
+      }
+      if let Some(backup_path) = backup {
+        if backup_path.symlink_metadata().is_ok() && target.symlink_metadata().is_err() {
+          kfs::move_path(backup_path, target)?;
+          kfs::remove_empty_parents(backup_path, &kfs::get_backup_root()?)?;
+        }
+      }
+    },
+    kplan::Action::Mkdir { path } => {
+      if path.symlink_metadata().is_err() {
+        std::fs::create_dir(path)?;
+      }
+    },
+    kplan::Action::Backup { target, backup } => {
+      if target.symlink_metadata().is_ok() {
+        backup_config(target, backup)?;
+      }
+    },
+    kplan::Action::Link { config, target, mode } => {
+      if !kfs::is_installed(config, target, *mode) {
+        println!("  + {:?}", target);
+        install_config(config, target, *mode)?;
+      }
+    },
+    kplan::Action::Render { template, config, contents, .. } => {
+      if std::fs::read_to_string(config).ok().as_ref() != Some(contents) {
+        ktemplate::write_rendered(template, config, contents)?;
+      }
+    },
+    kplan::Action::Decrypt { secret, config, contents, .. } => {
+      let contents = match contents {
+        Some(contents) => contents.to_owned(),
+        None => ksecret::decrypt(secret)?,
+      };
+      if std::fs::read(config).ok().as_ref() != Some(&contents) {
+        ksecret::write_decrypted(config, &contents)?;
+      }
+    },
+    kplan::Action::Skip { .. } => { },
+  }
+  return Ok(());
+}
+
+/// Reverses each action within the journal that was applied or interrupted, starting with the latest
+/// + Configurations installed by the interrupted action are removed even if they are incomplete
+/// + Links installed by earlier actions are only removed if they were not modified since
+/// + Templates rendered and secrets decrypted by the installation are removed, unless they existed before it
+fn rollback_install(journal: &kjournal::Journal) -> Result<()> {
+  let applied = std::cmp::min(journal.step + 1, journal.actions.len());
+  for (step, action) in journal.actions[..applied].iter().enumerate().rev() {
+    match action {
+      kplan::Action::Link { config, target, mode } => {
+        if target.symlink_metadata().is_ok()
+            && (step == journal.step || kfs::is_installed(config, target, *mode)) {
+          kfs::remove_config(target)?;
+        }
+      },
+      kplan::Action::Backup { target, backup } => {
+        if backup.symlink_metadata().is_ok() && target.symlink_metadata().is_err() {
+          kfs::move_path(backup, target)?;
+          kfs::remove_empty_parents(backup, &journal.backup_dir)?;
+        }
+      },
+      kplan::Action::Mkdir { path } => {
+        kfs::remove_empty_dirs(&vec![path.to_owned()])?;
+      },
+      kplan::Action::Remove { config, target, mode, backup } => {
+        // Move a restored backup back into the backup directory, and install the config again
+        if let Some(backup_path) = backup {
+          if target.symlink_metadata().is_ok() && backup_path.symlink_metadata().is_err()
+              && !kfs::is_installed(config, target, *mode) {
+            kfs::create_dir_all(&backup_path.parent().unwrap().to_path_buf())?;
+            kfs::move_path(target, backup_path)?;
+          }
+        }
+        if target.symlink_metadata().is_err() {
+          install_config(config, target, *mode)?;
+        }
+      },
+      // Rendered templates and decrypted secrets that did not exist before the installation are removed
+      kplan::Action::Render { config, created, .. } | kplan::Action::Decrypt { config, created, .. } => {
+        let temp_path = kfs::get_temp_path(config);
+        if temp_path.is_file() {
+          std::fs::remove_file(&temp_path)?;
+        }
+        if *created && config.is_file() {
+          std::fs::remove_file(config)?;
+        }
+        for root in [ktemplate::get_rendered_root()?, ksecret::get_secrets_root()?].iter() {
+          if config.starts_with(root) {
+            kfs::remove_empty_parents(config, root)?;
+          }
+        }
+      },
+      kplan::Action::Skip { .. } => { },
+    }
+  }
+
+  // Remove the backup directory if nothing was left within it
+  if journal.backup_dir.is_dir() && kfs::dir_entries(&journal.backup_dir)? == 0 {
+    std::fs::remove_dir(&journal.backup_dir)?;
+  }
   return Ok(());
 }
 
+/// Checks for a journal left behind by an installation that was interrupted
+/// + If --recover is set, the installation is completed or rolled back without prompting
+/// + If --force is set without --recover, the installation is rolled back, since completing it is never assumed
+/// + Otherwise, prompt user to complete or roll back the installation
+/// + Dry runs report the interrupted installation without recovering it
+fn recover_install(args: &kcli::Cli) -> Result<()> {
+  let mut journal = match kjournal::Journal::load()? {
+    Some(journal) => journal,
+    None => return Ok(()),
+  };
+  let mut msg = format!("An interrupted installation of {:?} into {:?} was found, stopped at:",
+                        journal.dotfiles, journal.install_dir);
+  msg += match journal.actions.get(journal.step) {
+    Some(action) => format!("\n  {}", action),
+    None => "\n  recording the installation".to_owned(),
+  }.as_str();
+  if args.dry_run {
+    println!("{}\nRun kot without --dry-run to complete or roll back the installation", msg);
+    return Ok(());
+  }
+  msg += "\nComplete the installation? Enter Y/y to complete or N/n to roll it back: ";
+
+  let complete = match args.recover {
+    Some(recover) => recover == kcli::Recover::Complete,
+    None if args.force => false,
+    None => kio::prompt(msg),
+  };
+  return match complete {
+    true => {
+      // Hooks are not run again, since the dotfiles may have changed since the installation was interrupted
+      println!("Completing installation:");
+      apply_install(&mut journal)?;
+      finish_install(&journal)
+    },
+    false => {
+      println!("Rolling back installation");
+      rollback_install(&journal)?;
+      kjournal::Journal::finish()
+    },
+  };
+}
+
+/// Installs a single configuration, removing anything left at target if the first attempt fails
+fn install_config(config_path: &PathBuf, target_path: &PathBuf,
+                  mode: kfs::InstallMode) -> Result<()> {
+  return kfs::install_config(config_path, target_path, mode)
+      .or_else(|err| -> Result<()> {
+        eprintln!("Error: Unable to install config {:?} -> {:?} as {} ({:?})",
+                  target_path, config_path, mode, err);
+
+        // Attempt to remove the file or directory first, and then install the new config
+        if target_path.symlink_metadata().is_ok() {
+          kfs::remove_config(target_path)?;
+        }
+        // Try to install the config again, if failure exit with error
+        kfs::install_config(config_path, target_path, mode).or_else(|err| {
+          eprintln!("Error: Unable to install config: {:?} -> {:?}",
+                    target_path, config_path);
+          return Err(err);
+        })?;
+
+        return Ok(());
+      });
+}
+
 /// Handles collisions between existing files and dotfiles we're installing
 /// + If --force is not set, prompt user to continue based on conflicts found
 /// + If --force is set or user chooses to continue,
-///     move conflicting files to a backup directory
-fn handle_collisions(args: &mut kcli::Cli,
-                     config_map: &kfs::HashMap<PathBuf, PathBuf>) -> Result<()> {
-  // Check if we found any collisions in the configurations
-  return match check_collisions(&config_map) {
-    None => Ok(()), // There were no collisions, configurations pass pre-install checks
-    Some(conflicts) => {
-      args.conflicts = conflicts.to_owned();
-      // Ask client if they would like to abort given the config collisions
-      let mut msg = format!("The following configurations already exist:");
-      for config in conflicts.iter() {
-        msg += format!("\n  {:?}", config).as_str();
-      }
-      msg += format!("\nIf you continue, backups will be made in {:?}. \
-                      Any configurations there will be overwritten.\
-                      \nContinue? Enter Y/y or N/n: ",
-                     args.backup_dir.as_ref().unwrap()).as_str();
-
-      // If the --force flag is set, short-circuit boolean and skip prompt
-      match args.force || kio::prompt(msg) {
-        true => {
-          // Backup each conflicting config at the install location
-          for backup_target in conflicts.iter() {
-            backup_config(backup_target, &args)?;
-          }
-          Ok(())
-        },
-        false => err!(Other("User aborted installation".to_string()), "Aborted".to_string())
-      }
-    }
+///     conflicting files are moved to a backup directory during installation
+fn handle_collisions(args: &kcli::Cli, conflicts: &[PathBuf], backup_dir: &Path)
+                     -> Result<()> {
+  // Ask client if they would like to abort given the config collisions
+  let mut msg = format!("The following configurations already exist:");
+  for config in conflicts.iter() {
+    msg += format!("\n  {:?}", config).as_str();
+  }
+  msg += format!("\nIf you continue, backups will be made in {:?}. \
+                  Any configurations there will be overwritten.\
+                  \nContinue? Enter Y/y or N/n: ",
+                 backup_dir).as_str();
+
+  // If the --force flag is set, short-circuit boolean and skip prompt
+  return match args.force || kio::prompt(msg) {
+    true => Ok(()),
+    false => err!(Other("User aborted installation".to_string()), "Aborted".to_string())
   };
 }
 
 /// Checks if any config to install collides with existing files or directories
 /// + Returns a list of collisions within Some(), else returns None
-pub fn check_collisions(config_map: &HashMap<PathBuf, PathBuf>)
-                        -> Option<Vec<PathBuf>> {
+pub fn check_collisions(actions: &[kplan::Action]) -> Option<Vec<PathBuf>> {
   let mut config_conflicts = vec![];
-  for (_path, target_config) in config_map.iter() {
-    // If the target configuration file or directory already exists
-    if target_config.exists() {
-      config_conflicts.push(target_config.to_owned());
+  for action in actions.iter() {
+    // If the target configuration file or directory already exists, the plan backs it up
+    // + Configurations installed by a previous installation are not conflicts
+    if let kplan::Action::Backup { target, .. } = action {
+      config_conflicts.push(target.to_owned());
     }
   }
   if !config_conflicts.is_empty() {
@@ -161,76 +446,1046 @@ pub fn check_collisions(config_map: &HashMap<PathBuf, PathBuf>)
   return None;
 }
 
+/// State of a single configuration at its location in the install directory
+#[derive(Debug, PartialEq)]
+pub enum ConfigStatus {
+  /// Target is a link or unmodified copy of the configuration created by kot
+  Installed,
+  /// Target exists, but is not a link or unmodified copy of the configuration
+  Replaced,
+  /// Target is a link that does not resolve to an existing file or directory
+  Broken,
+  /// Nothing exists at target
+  NotInstalled,
+}
+
+impl std::fmt::Display for ConfigStatus {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    let status = match self {
+      ConfigStatus::Installed => "installed",
+      ConfigStatus::Replaced => "replaced",
+      ConfigStatus::Broken => "broken",
+      ConfigStatus::NotInstalled => "not installed",
+    };
+    write!(f, "{}", status)
+  }
+}
+
+/// Compares a configuration to the file or directory at its target install path
+/// + Unlike check_collisions, links are inspected without following them
+pub fn check_status(config_path: &PathBuf, target_path: &PathBuf,
+                    mode: kfs::InstallMode) -> ConfigStatus {
+  let meta = match target_path.symlink_metadata() {
+    Ok(meta) => meta,
+    Err(_) => return ConfigStatus::NotInstalled,
+  };
+  // exists() follows links, so a link to a missing file or directory is broken
+  if meta.file_type().is_symlink() && !target_path.exists() {
+    return ConfigStatus::Broken;
+  }
+  return match kfs::is_installed(config_path, target_path, mode) {
+    true => ConfigStatus::Installed,
+    false => ConfigStatus::Replaced,
+  };
+}
+
 // Creates a backup of configurations that conflict
 // + Backup directory location is specified by CLI --backup-dir
-// TODO: .kotignore in dotfiles repo to specify files to not install / backup
-// TODO: .kotrc in dotfiles repo or home dir to set backup-dir and install-dir?
-fn backup_config(config_path: &kfs::PathBuf, args: &kcli::Cli) -> Result<()> {
-  let mut backup_path = args.backup_dir.as_ref().unwrap().to_owned();
+// + Backups mirror the layout of the configurations within the install directory
+fn backup_config(config_path: &PathBuf, backup_path: &PathBuf) -> Result<()> {
+  kfs::create_dir_all(&backup_path.parent().unwrap().to_path_buf())?;
 
   // Check if the configuration we're backing up is a directory or a single file
   match config_path.is_dir() {
     true => {
       // Copy directory with recursion using move_dir() wrapper function
+      // + Existing backups were either stashed by --force or left by an interrupted backup
       let mut options = fs_extra::dir::CopyOptions::new();
       options.copy_inside = true;
-      options.overwrite = args.force;
-      kfs::move_dir(config_path, &backup_path, Some(&options))?;
+      options.overwrite = true;
+      kfs::move_dir(config_path, backup_path, Some(&options))?;
     }
     false => {
-      backup_path.push(config_path.file_name().unwrap());
       // Copy single configuration file
-      kfs::move_file(config_path, &backup_path)?;
+      kfs::move_file(config_path, backup_path)?;
     }
   }
   return Ok(());
 }
 
-// Loops through dotfiles to restore backup files or delete unused configs
-pub fn uninstall_configs(args: &kcli::Cli) -> Result<()> {
-  //
-  // Replace previous configs we stored in backup_dir
-  for config in args.backup_dir.as_ref().unwrap().read_dir()? {
-    match config.as_ref().unwrap().path().is_dir() {
-      true => {
-        let mut options = fs_extra::dir::CopyOptions::new();
-        options.copy_inside = true;
-        options.overwrite = args.force;
-        kfs::move_dir(&config.as_ref().unwrap().path(), &args.install_dir,
-                      Some(&options)
-        )?;
-      },
-      false => {
-        kfs::move_file(&config.unwrap().path(), &args.install_dir)?;
+/// Reverses an installation recorded in the kot manifest
+/// + Removes links and copies of configurations that were not modified since installation
+/// + Moves configurations from the backup directory for the installation back into place
+/// + Links that were replaced since installation are left untouched, along with their backups
+/// + Each package installed from the dotfiles is uninstalled, unless packages are selected by --package
+pub fn uninstall(args: &kcli::Cli) -> Result<()> {
+  let mut manifest = kmanifest::Manifest::load()?;
+  let indices = manifest.find_installs(&args.install_dir, args.dotfiles.as_ref(), &args.packages);
+  if indices.is_empty() {
+    err!(
+      ErrorKind::ConfigError(format!("No installation found in {:?}", args.install_dir)),
+      format!("Unable to uninstall dotfiles: {:?}", args.dotfiles)
+    );
+  }
+
+  warn_dependents(&manifest, &indices);
+
+  println!("Uninstalling configs:");
+  for index in indices.into_iter().rev() {
+    let hooks = match args.no_hooks {
+      true => None,
+      false => find_hooks(&manifest.installs[index]),
+    };
+    // Installations that were already reversed are saved to the manifest before any hook error is returned
+    if let Some((dotfiles, package)) = &hooks {
+      if let Err(e) = package.hooks.run(khook::Hook::PreUninstall, dotfiles, package, &args.install_dir) {
+        manifest.save()?;
+        return Err(e);
+      }
+    }
+    let install = manifest.installs.remove(index);
+    uninstall_package(&install)?;
+    if let Some((dotfiles, package)) = &hooks {
+      if let Err(e) = package.hooks.run(khook::Hook::PostUninstall, dotfiles, package, &args.install_dir) {
+        manifest.save()?;
+        return Err(e);
       }
+    }
+  }
+  return manifest.save();
+}
+
+/// Warns about installed packages that depend on packages being uninstalled
+/// + Packages are still uninstalled, since their dependents may not need them anymore
+fn warn_dependents(manifest: &kmanifest::Manifest, indices: &[usize]) {
+  let uninstalling: Vec<_> = indices.iter().map(|index| &manifest.installs[*index]).collect();
+  for install in uninstalling.iter() {
+    let (name, repo) = match (&install.package, krepo::Repo::load(&install.source)) {
+      (Some(name), Ok(repo)) => (name, repo),
+      _ => continue,
     };
+    let dependents: Vec<_> = repo.get_dependents(name).into_iter()
+        .filter(|dependent| {
+          !uninstalling.iter().any(|i| i.source == install.source && i.package.as_ref() == Some(dependent))
+              && manifest.find_package(&install.install_dir, &install.source, Some(dependent)).is_some()
+        })
+        .collect();
+    if !dependents.is_empty() {
+      eprintln!("Warning: Package {:?} is a dependency of installed packages: {}", name, dependents.join(", "));
+    }
   }
+}
 
-  //
-  // Remove configurations only required by the dotfiles we attempted to install
-
-  // Check each config in the dotfiles we want to uninstall
-  let dotfile_path = match args.is_repo {
-    true => args.clone_dir.as_ref().unwrap(),
-    false => &args.dotfiles
-  };
-
-  for dotfile in dotfile_path.read_dir()? {
-    let path = dotfile.unwrap().path();
-    // If the configuration was not a conflict initially
-    //   then we didn't have it before we installed; It is not being used
-    if !args.conflicts.contains(&path) {
-      let mut unused_config: PathBuf = args.install_dir.to_owned();
-      unused_config.push(std::path::Path::new(&path.file_name().unwrap()));
-      // Verify the file was already installed before we hit an error
-      if !unused_config.exists() {
+/// Finds the package within the local dotfiles for a recorded installation, to run its uninstall hooks
+/// + Returns None if the dotfiles or package no longer exist
+/// + Returns None if the dotfiles were installed as a whole, but now list packages
+fn find_hooks(install: &kmanifest::Install) -> Option<(PathBuf, krepo::Package)> {
+  let repo = krepo::Repo::load(&install.source).ok()?;
+  let package = match &install.package {
+    Some(name) => repo.get_package(&install.source, name).ok()?,
+    // Dotfiles installed as a whole that now list packages have no hooks for the installation
+    None if repo.packages.is_empty() => repo.get_packages(&install.source, &[]).ok()?.pop()?,
+    None => return None,
+  };
+  return Some((install.source.to_owned(), package));
+}
+
+/// Reverses a single installation recorded in the kot manifest
+fn uninstall_package(install: &kmanifest::Install) -> Result<()> {
+  let mut skipped = vec![];
+  for link in &install.links {
+    // Only remove configurations that kot installed and were not modified since
+    if kfs::is_installed(&link.config, &link.target, link.mode) {
+      println!("  - {:?}", link.target);
+      kfs::remove_config(&link.target)?;
+    }
+    else if link.target.symlink_metadata().is_ok() {
+      skipped.push(link.target.to_owned());
+      continue;
+    }
+
+    // Rendered templates and decrypted secrets are removed along with the configuration installed from them
+    for root in [ktemplate::get_rendered_root()?, ksecret::get_secrets_root()?].iter() {
+      if link.config.starts_with(root) && link.config.is_file() {
+        std::fs::remove_file(&link.config)?;
+        kfs::remove_empty_parents(&link.config, root)?;
+      }
+    }
+
+    // Move the configuration we replaced during installation back into place
+    match &link.backup {
+      Some(backup_path) if backup_path.symlink_metadata().is_ok() => {
+        println!("  + {:?}", link.target);
+        kfs::move_path(backup_path, &link.target)?;
+        kfs::remove_empty_parents(backup_path, &kfs::get_backup_root()?)?;
+      },
+      _ => { }
+    }
+  }
+
+  // Remove directories that were created to hold configurations
+  kfs::remove_empty_dirs(&install.dirs)?;
+
+  if !skipped.is_empty() {
+    eprintln!("Warning: The following configurations were modified since installation and were not removed:");
+    for target in skipped.iter() {
+      eprintln!("  {:?}", target);
+    }
+    eprintln!("Any backups of these configurations remain in {:?}", install.backup_dir);
+  }
+  // Clean up the backup directory if every backup was restored
+  if install.backup_dir.exists() && kfs::dir_entries(&install.backup_dir)? == 0 {
+    std::fs::remove_dir(&install.backup_dir)?;
+  }
+  return Ok(());
+}
+
+/// Restores configurations from a backup into the install directory
+/// + If no backup is provided, lists the backups available within the kot data directory
+/// + If no files are provided, every configuration within the backup is restored
+pub fn restore(args: &kcli::Cli, backup: Option<&PathBuf>, files: &Vec<PathBuf>)
+               -> Result<()> {
+  let backup_root = kfs::get_backup_root()?;
+  let backup = match backup {
+    Some(backup) => backup,
+    None => return list_backups(&backup_root),
+  };
+
+  // Backups can be referred to by name or by path to the backup directory
+  let backup_dir = match backup_root.join(backup) {
+    dir if dir.is_dir() => dir,
+    _ => kfs::abs(backup)?,
+  };
+  let mut configs = files.to_owned();
+  if configs.is_empty() {
+    for entry in backup_dir.read_dir()? {
+      configs.push(PathBuf::from(entry?.file_name()));
+    }
+  }
+
+  // Find the target of every backup before restoring any of them
+  let mut manifest = kmanifest::Manifest::load()?;
+  let mut restores = vec![];
+  for config in configs.iter() {
+    let backup_path = backup_dir.join(config);
+    if backup_path.symlink_metadata().is_err() {
+      err!(
+        ErrorKind::FileError(format!("No backup of {:?} in {:?}", config, backup_dir)),
+        "Unable to restore configuration".to_owned()
+      );
+    }
+    restores.extend(get_restore_targets(args, &manifest, &backup_dir, &backup_path)?);
+  }
+
+  let mut skipped = vec![];
+  println!("Restoring configs:");
+  for (backup_path, target) in restores.iter() {
+    restore_backup(backup_path, target, &mut manifest, &mut skipped)?;
+    kfs::remove_empty_parents(backup_path, &backup_dir)?;
+  }
+  manifest.save()?;
+
+  if !skipped.is_empty() {
+    eprintln!("Warning: The following configurations already exist and were not restored:");
+    for target in skipped.iter() {
+      eprintln!("  {:?}", target);
+    }
+  }
+  // Clean up the backup directory if every backup was restored
+  if kfs::dir_entries(&backup_dir)? == 0 {
+    std::fs::remove_dir(&backup_dir)?;
+  }
+  return Ok(());
+}
+
+/// Prints each backup within backup_root along with the number of configurations it contains
+fn list_backups(backup_root: &PathBuf) -> Result<()> {
+  let mut backups = vec![];
+  if backup_root.exists() {
+    for entry in backup_root.read_dir()? {
+      backups.push(entry?.path());
+    }
+  }
+  backups.sort();
+
+  println!("Available backups in {:?}:", backup_root);
+  for backup in backups.iter() {
+    println!("  {} ({} configurations)",
+             backup.file_name().unwrap().to_string_lossy(), kfs::dir_entries(backup)?);
+  }
+  return Ok(());
+}
+
+/// Returns each backup within backup_path, along with the target it is restored to
+/// + Backups recorded in the manifest are restored to the target they were moved from,
+///     which is outside of the install directory for targets listed with an absolute path, see kfs::get_backup_path()
+/// + Other backups are restored at the same path within the install directory as within backup_dir
+fn get_restore_targets(args: &kcli::Cli, manifest: &kmanifest::Manifest, backup_dir: &Path,
+                       backup_path: &Path) -> Result<Vec<(PathBuf, PathBuf)>> {
+  if let Some(target) = manifest.find_backup_target(backup_path) {
+    return Ok(vec![(backup_path.to_owned(), target)]);
+  }
+  // Directories containing recorded backups are only created within the backup directory to hold them
+  if !manifest.has_backups_within(backup_path) {
+    let relative = backup_path.strip_prefix(backup_dir).unwrap();
+    return Ok(vec![(backup_path.to_owned(), args.install_dir.join(relative))]);
+  }
+  let mut targets = vec![];
+  for entry in backup_path.read_dir()? {
+    targets.extend(get_restore_targets(args, manifest, backup_dir, &entry?.path())?);
+  }
+  return Ok(targets);
+}
+
+/// Moves a single backup to target, replacing any link kot installed there
+/// + Backups of directories are merged into directories that exist at target
+/// + Targets that exist and were not installed by kot are added to skipped
+fn restore_backup(backup_path: &PathBuf, target: &PathBuf,
+                  manifest: &mut kmanifest::Manifest,
+                  skipped: &mut Vec<PathBuf>) -> Result<()> {
+  // Remove the link kot installed where the backed up configuration used to be
+  if let Some((install, link)) = manifest.find_link(target) {
+    println!("  - {:?}", target);
+    kfs::remove_config(target)?;
+    manifest.remove_link(install, link);
+  }
+
+  let target_meta = match target.symlink_metadata() {
+    Ok(meta) => meta,
+    Err(_) => {
+      println!("  + {:?}", target);
+      return kfs::move_path(backup_path, target);
+    }
+  };
+  if backup_path.is_dir() && target_meta.is_dir() {
+    for entry in backup_path.read_dir()? {
+      let name = entry?.file_name();
+      restore_backup(&backup_path.join(&name), &target.join(&name), manifest, skipped)?;
+    }
+    if kfs::dir_entries(backup_path)? == 0 {
+      std::fs::remove_dir(backup_path)?;
+    }
+    return Ok(());
+  }
+  skipped.push(target.to_owned());
+  return Ok(());
+}
+
+/// Prints the status of each configuration within the dotfiles
+/// + Configurations recorded in the manifest that are no longer within the dotfiles are also reported
+pub fn status(args: &kcli::Cli) -> Result<()> {
+  let manifest = kmanifest::Manifest::load()?;
+  let installs: Vec<_> = manifest.find_installs(&args.install_dir, args.dotfiles.as_ref(), &args.packages)
+      .into_iter().map(|index| &manifest.installs[index]).collect();
+
+  // Find the local dotfiles to compare against the install directory
+  let dotfiles = find_dotfiles(args, installs.first().cloned())?;
+
+  // Configurations recorded by the installation use the mode they were installed with
+  let mut configs = vec![];
+  for install in installs.iter() {
+    for link in install.links.iter() {
+      configs.push((link.config.to_owned(), link.target.to_owned(), link.mode));
+    }
+  }
+  // Templates are rendered into a directory named after the dotfiles that were installed
+  let name = match installs.first() {
+    Some(install) => install.name.to_owned(),
+    None => args.get_name()?,
+  };
+  let repo = krepo::Repo::load(&dotfiles)?;
+  for package in repo.get_packages(&dotfiles, &args.packages)? {
+    for (config_path, target_path) in kplan::get_config_map(args, &dotfiles, &package)? {
+      let source = kplan::get_install_source(&name, &dotfiles, &package, &config_path)?;
+      // Skip configurations within directories that were copied by the installation
+      if configs.iter().any(|(config, _, _)| source.starts_with(config)) {
         continue;
       }
+      let mode = args.get_mode(&dotfiles, &package, &config_path);
+      configs.push((source, target_path, mode));
+    }
+  }
+  configs.sort_by(|a, b| a.1.cmp(&b.1));
 
-      // Remove the unused config from install_dir
-      std::fs::remove_file(unused_config)?;
+  println!("Status of {:?} in {:?}:", dotfiles, args.install_dir);
+  for (config_path, target_path, mode) in configs.iter() {
+    println!("  {:<14} {:?}",
+             check_status(config_path, target_path, *mode).to_string(), target_path);
+  }
+  return Ok(());
+}
+
+/// Prints each package listed within the dotfiles, and if it is installed within the install directory
+/// + Dotfiles that do not list any packages are reported as a whole
+pub fn list(args: &kcli::Cli) -> Result<()> {
+  let manifest = kmanifest::Manifest::load()?;
+  let install = manifest.find_latest(&args.install_dir, args.dotfiles.as_ref())
+      .map(|index| &manifest.installs[index]);
+  let dotfiles = find_dotfiles(args, install)?;
+
+  let repo = krepo::Repo::load(&dotfiles)?;
+  if repo.packages.is_empty() {
+    let installed = manifest.find_package(&args.install_dir, &dotfiles, None).is_some();
+    println!("No packages are listed within {:?}; The dotfiles are installed as a whole and are {}",
+             dotfiles.join(krepo::KOT_TOML), if installed { "installed" } else { "not installed" });
+    return Ok(());
+  }
+  println!("Packages in {:?}:", dotfiles);
+  for package in repo.packages.keys() {
+    let installed = manifest.find_package(&args.install_dir, &dotfiles, Some(package)).is_some();
+    println!("  {:<14} {}", package, if installed { "installed" } else { "not installed" });
+  }
+  return Ok(());
+}
+
+/// Updates the clone of a dotfiles repository installed into the install directory, and installs it again
+/// + Clones with local modifications are not updated unless allow_modified is set
+/// + Only the packages that were installed from the dotfiles are installed again, unless packages are selected
+/// + Configurations that were removed from the dotfiles are unlinked by the new installation, see kplan::plan_install()
+pub fn update(args: &mut kcli::Cli, allow_modified: bool) -> Result<()> {
+  let manifest = kmanifest::Manifest::load()?;
+  let indices = manifest.find_installs(&args.install_dir, args.dotfiles.as_ref(), &args.packages);
+  let install = match indices.last() {
+    Some(index) => &manifest.installs[*index],
+    None => err!(
+      ErrorKind::ConfigError(format!("No installation found in {:?}", args.install_dir)),
+      format!("Unable to update dotfiles: {:?}", args.dotfiles)
+    ),
+  };
+  if !kcli::is_repo_url(&install.dotfiles)? {
+    err!(
+      ErrorKind::ConfigError(format!("Dotfiles {:?} were not installed from a repository URL", install.dotfiles)),
+      "Only dotfiles cloned by kot can be updated".to_owned()
+    );
+  }
+  let clone_dir = install.source.to_owned();
+  if !allow_modified && kgit::is_modified(&clone_dir)? {
+    err!(
+      ErrorKind::GitError(format!("Repository {:?} has local modifications", clone_dir)),
+      "Commit or discard the changes, or set --allow-modified to update the repository anyway".to_owned()
+    );
+  }
+
+  // A dry run never updates the clone, since the configurations can only be planned once it is updated
+  if args.dry_run {
+    println!("Planned actions for {:?} in {:?}:", install.dotfiles, args.install_dir);
+    println!("  {:<7} {:?}", "update", clone_dir);
+    println!("The repository must be updated before configurations can be planned");
+    return Ok(());
+  }
+  let previous = kgit::get_commit(&clone_dir)?;
+  kgit::update(&clone_dir)?;
+  let commit = kgit::get_commit(&clone_dir)?;
+  match previous == commit {
+    true => println!("Repository {:?} is up to date at {}", clone_dir, commit),
+    false => println!("Updated repository {:?} from {} to {}", clone_dir, previous, commit),
+  }
+
+  // Install the packages that were installed before the update
+  if args.packages.is_empty() {
+    args.packages = indices.iter().filter_map(|index| manifest.installs[*index].package.to_owned()).collect();
+  }
+  args.dotfiles = Some(install.dotfiles.to_owned());
+  args.is_repo = true;
+  args.clone_dir = Some(clone_dir);
+  args.commit = Some(commit);
+  return install_or_rollback(args);
+}
+
+/// Moves configurations from the install directory into the dotfiles, and installs them in their place
+/// + Each configuration is moved to the path within the dotfiles it would be installed from, see get_adopt_source()
+/// + Adopted configurations are recorded in the manifest along with the package they were moved into
+/// + If commit is set, the adopted configurations are committed within the dotfiles repository
+pub fn adopt(args: &kcli::Cli, files: &[PathBuf], commit: bool) -> Result<()> {
+  let mut manifest = kmanifest::Manifest::load()?;
+  let latest = manifest.find_latest(&args.install_dir, args.dotfiles.as_ref())
+      .map(|index| manifest.installs[index].to_owned());
+  let dotfiles = find_dotfiles(args, latest.as_ref())?;
+
+  // Configurations can only be moved into a single package
+  let repo = krepo::Repo::load(&dotfiles)?;
+  let package = match (args.packages.as_slice(), repo.packages.is_empty()) {
+    ([name], _) => repo.get_package(&dotfiles, name)?,
+    ([], true) => repo.get_packages(&dotfiles, &[])?.pop().unwrap(),
+    _ => err!(
+      ErrorKind::ConfigError(format!("Unable to choose a package within {:?}", dotfiles)),
+      "Select the package to move configurations into with --package".to_owned()
+    ),
+  };
+
+  // Check every configuration before moving any of them
+  // + Targets listed within kot.toml whose configuration is not within the dotfiles yet are not planned
+  let targets = package.get_targets(&args.install_dir)?;
+  let mut planned_package = package.to_owned();
+  planned_package.targets.retain(|source, _| package.dir.join(source).symlink_metadata().is_ok());
+  let planned = kplan::get_config_map(args, &dotfiles, &planned_package)?;
+  let mut adopted = vec![];
+  for file in files.iter() {
+    let target = get_adopt_target(file)?;
+    if adopted.iter().any(|(_, adopted_target)| adopted_target == &target) {
+      continue;
+    }
+    let source = get_adopt_source(args, &dotfiles, &package, &targets, &planned, &target)?;
+    adopted.push((source, target));
+  }
+
+  if args.dry_run {
+    println!("Planned actions for adopting into {:?}:", package.dir);
+    for (source, target) in adopted.iter() {
+      println!("  {:<7} {:?} -> {:?}", "adopt", target, source);
+      println!("  {:<7} {:?} -> {:?} ({})", "link", target, source, args.get_mode(&dotfiles, &package, source));
     }
+    return Ok(());
   }
 
+  println!("Adopting configs into {:?}:", package.dir);
+  let mut links = vec![];
+  let result = adopted.iter().try_for_each(|(source, target)| -> Result<()> {
+    kfs::create_dir_all(&source.parent().unwrap().to_path_buf())?;
+    kfs::move_path(target, source)?;
+    let mode = args.get_mode(&dotfiles, &package, source);
+    // Move the configuration back if it can not be installed, so it is never lost
+    if let Err(e) = kfs::install_config(source, target, mode) {
+      kfs::move_path(source, target)?;
+      return Err(e);
+    }
+    println!("  + {:?} -> {:?}", target, source);
+    links.push(kmanifest::Link {
+      config: source.to_owned(), target: target.to_owned(), mode, backup: None
+    });
+    return Ok(());
+  });
+
+  // Add the links to the installation of the package, or record a new installation if it was never installed
+  if !links.is_empty() {
+    match manifest.find_package(&args.install_dir, &dotfiles, package.name.as_ref()) {
+      Some(index) => {
+        let install = &mut manifest.installs[index];
+        install.links.retain(|link| !links.iter().any(|adopted| adopted.target == link.target));
+        install.links.append(&mut links);
+      },
+      None => {
+        let name = latest.as_ref().map_or(dotfiles.file_name().unwrap().to_string_lossy().to_string(),
+                                          |install| install.name.to_owned());
+        manifest.installs.push(kmanifest::Install {
+          package: package.name.to_owned(),
+          dotfiles: latest.as_ref().map_or(dotfiles.to_owned(), |install| install.dotfiles.to_owned()),
+          source: dotfiles.to_owned(),
+          commit: None,
+          install_dir: args.install_dir.to_owned(),
+          backup_dir: kfs::get_backup_root()?.join(format!(
+            "{}{}:{}", name, package.name.as_ref().map_or("".to_owned(), |name| ".".to_owned() + name), args.timestamp
+          )),
+          timestamp: args.timestamp.to_owned(),
+          dirs: vec![],
+          links,
+          name,
+        });
+      },
+    }
+  }
+  // Configurations adopted before any error are still recorded, since they were moved into the dotfiles
+  manifest.save()?;
+  result?;
+
+  if commit {
+    let sources: Vec<_> = adopted.iter().map(|(source, _)| source.to_owned()).collect();
+    let names: Vec<_> = sources.iter()
+        .map(|source| source.strip_prefix(&dotfiles).unwrap().to_string_lossy().to_string()).collect();
+    let hash = kgit::commit(&dotfiles, &sources, &format!("Adopt {}", names.join(", ")))?;
+    println!("Committed adopted configs as {}", hash);
+  }
   return Ok(());
 }
+
+/// Returns the absolute path to a configuration provided to adopt, without following it if it is a link
+fn get_adopt_target(file: &PathBuf) -> Result<PathBuf> {
+  let path = std::env::current_dir()?.join(file);
+  let name = match path.file_name() {
+    Some(name) if path.symlink_metadata().is_ok() => name.to_owned(),
+    _ => err!(
+      ErrorKind::FileError(format!("No configuration at {:?}", file)),
+      "Provide the path to a file or directory to adopt".to_owned()
+    ),
+  };
+  return Ok(kfs::abs(&path.parent().unwrap().to_path_buf())?.join(name));
+}
+
+/// Returns the path within a package that a configuration at target would be installed from
+/// + Targets listed within kot.toml, and paths within them, use the configuration listed for them
+/// + Other configurations use the same path within the package as within the install directory, see kfs::get_source_name()
+/// + Configurations that are already within the dotfiles, or that would replace a file within the dotfiles, are an error
+/// + Targets planned for the package, see kplan::get_config_map(), are an error along with paths within them,
+///     so a template, secret, or profile installed at the same target never conflicts with the adopted configuration
+/// + Sources that are ignored, or would be installed at another name, are an error since they would never be installed
+fn get_adopt_source(args: &kcli::Cli, dotfiles: &PathBuf, package: &krepo::Package,
+                    targets: &[(PathBuf, PathBuf)], planned: &kfs::HashMap<PathBuf, PathBuf>,
+                    target: &PathBuf) -> Result<PathBuf> {
+  let relative = match target.strip_prefix(&args.install_dir) {
+    Ok(relative) => relative,
+    Err(_) => err!(
+      ErrorKind::ConfigError(format!("Configuration {:?} is not within {:?}", target, args.install_dir)),
+      "Only configurations within the install directory can be adopted".to_owned()
+    ),
+  };
+  if target.canonicalize().is_ok_and(|path| path.starts_with(dotfiles)) {
+    err!(
+      ErrorKind::ConfigError(format!("Configuration {:?} is already installed from {:?}", target, dotfiles)),
+      "Only configurations that are not within the dotfiles can be adopted".to_owned()
+    );
+  }
+  if let Some((config, planned_target)) = planned.iter()
+      .find(|(_, planned)| target.starts_with(planned) || planned.starts_with(target)) {
+    err!(
+      ErrorKind::ConfigError(format!("Configuration {:?} is installed from {:?} at {:?}", target, config, planned_target)),
+      "Only configurations that are not installed from the dotfiles can be adopted".to_owned()
+    );
+  }
+
+  // Names within a listed target, or within the package, are installed using the same rules as the dotfiles
+  let (dir, names) = match targets.iter().find(|(_, listed)| target.starts_with(listed)) {
+    Some((source, listed)) => (package.dir.join(source), target.strip_prefix(listed).unwrap()),
+    None => (package.dir.to_owned(), relative),
+  };
+  let mut source = dir;
+  let ignore = kignore::Ignore::new(dotfiles, args.include_repo_files, package.profiles.as_deref())?;
+  let names: Vec<_> = names.iter().collect();
+  for (index, name) in names.iter().enumerate() {
+    source.push(kfs::get_source_name(name, args.dot_prefix));
+    // Each name above the configuration itself is a directory within the dotfiles
+    let is_dir = index + 1 < names.len() || target.is_dir();
+    let installed_as = kfs::get_target_name(source.file_name().unwrap(), args.dot_prefix);
+    if installed_as.as_os_str() != *name || ignore.with_parents(dotfiles, &source)?.is_ignored(&source, is_dir) {
+      err!(
+        ErrorKind::ConfigError(format!("Configuration {:?} would not be installed from {:?}", target, source)),
+        "Check the .kotignore files and --dot-prefix used by the dotfiles".to_owned()
+      );
+    }
+  }
+  if !target.is_dir() && (ktemplate::is_template(target) || ksecret::is_secret(target)) {
+    err!(
+      ErrorKind::ConfigError(format!("Configuration {:?} would be installed without its suffix", target)),
+      "Rename the configuration, since templates and secrets are installed without their suffix".to_owned()
+    );
+  }
+  if source.symlink_metadata().is_ok() {
+    err!(
+      ErrorKind::ConfigError(format!("Configuration {:?} already exists within the dotfiles", source)),
+      format!("Remove {:?} from the dotfiles or install them to replace {:?}", source, target)
+    );
+  }
+  return Ok(source);
+}
+
+/// Encrypts file into a secret that is decrypted when the dotfiles are installed
+/// + If no output is provided, the secret is written next to file with ksecret::SECRET_SUFFIX appended
+/// + Existing secrets are only replaced if --force is set
+pub fn encrypt(args: &kcli::Cli, file: &PathBuf, output: Option<&PathBuf>) -> Result<()> {
+  let output = match output {
+    Some(output) => output.to_owned(),
+    None => {
+      let mut output = file.to_owned().into_os_string();
+      output.push(ksecret::SECRET_SUFFIX);
+      PathBuf::from(output)
+    },
+  };
+  if !file.is_file() {
+    err!(
+      ErrorKind::FileError(format!("No file {:?}", file)),
+      "Only files can be encrypted".to_owned()
+    );
+  }
+  if !output.to_string_lossy().ends_with(ksecret::SECRET_SUFFIX) {
+    err!(
+      ErrorKind::ConfigError(format!("{:?} does not end with {}", output, ksecret::SECRET_SUFFIX)),
+      "Secrets must end with .age to be decrypted when they are installed".to_owned()
+    );
+  }
+  if output.symlink_metadata().is_ok() && !args.force {
+    err!(
+      ErrorKind::FileError(format!("A secret already exists at {:?}", output)),
+      "Set the --force flag to replace it".to_owned()
+    );
+  }
+  ksecret::encrypt(file, &output)?;
+  println!("Encrypted {:?} to {:?}", file, output);
+  return Ok(());
+}
+
+/// Finds the local dotfiles used by a subcommand
+/// + Dotfiles that were installed are found using the installation recorded in the manifest
+fn find_dotfiles(args: &kcli::Cli, install: Option<&kmanifest::Install>) -> Result<PathBuf> {
+  return match (install, &args.dotfiles) {
+    (Some(install), _) => Ok(install.source.to_owned()),
+    (None, Some(dotfiles)) => kfs::abs(dotfiles),
+    (None, None) => err!(
+      ErrorKind::ConfigError(format!("No installation found in {:?}", args.install_dir)),
+      "Provide the dotfiles, or install them into the install directory first".to_owned()
+    ),
+  };
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use structopt::StructOpt;
+  use kfs::write;
+
+  /// Parses the arguments provided to the CLI, using install_dir as the install directory
+  /// + Cli::normalize() is not called, so the kot configuration files of the user running the tests are never read
+  fn get_args(install_dir: &Path, arguments: &[&str]) -> kcli::Cli {
+    let mut args = kcli::Cli::from_iter([&["kot"], arguments].concat());
+    args.install_dir = install_dir.to_owned();
+    args.timestamp = "2021-01-01T00:00:00".to_owned();
+    return args;
+  }
+
+  /// Returns each path within dir, along with the contents of files and the path each link points to
+  fn snapshot(dir: &Path) -> Vec<(PathBuf, String)> {
+    let mut entries = vec![];
+    for entry in std::fs::read_dir(dir).unwrap() {
+      let path = entry.unwrap().path();
+      let meta = path.symlink_metadata().unwrap();
+      if meta.file_type().is_symlink() {
+        entries.push((path.to_owned(), format!("-> {:?}", std::fs::read_link(&path).unwrap())));
+      }
+      else if meta.is_dir() {
+        entries.push((path.to_owned(), "/".to_owned()));
+        entries.extend(snapshot(&path));
+      }
+      else {
+        entries.push((path.to_owned(), std::fs::read_to_string(&path).unwrap()));
+      }
+    }
+    entries.sort();
+    return entries;
+  }
+
+  #[test]
+  fn uninstall_reverses_install() {
+    let dir = kfs::get_test_dir("kot-uninstall");
+    let (dot, home) = (dir.join("dot"), dir.join("home"));
+    write(&dot.join(".bashrc"), "bashrc");
+    write(&dot.join(".vimrc"), "vimrc");
+    write(&dot.join(".config/nvim/init.vim"), "init");
+    write(&home.join(".bashrc"), "conflict");
+    write(&home.join(".config/gtk-3.0/settings.ini"), "gtk");
+    let before = snapshot(&home);
+    install_or_rollback(&mut get_args(&home, &["--force", dot.to_str().unwrap()])).unwrap();
+    assert!(kfs::is_link_to(&home.join(".bashrc"), &dot.join(".bashrc")));
+    assert!(kfs::is_link_to(&home.join(".config/nvim"), &dot.join(".config/nvim")));
+    assert_eq!(std::fs::read_to_string(home.join(".bashrc")).unwrap(), "bashrc");
+
+    // Configurations replaced since installation are left in place
+    std::fs::remove_file(home.join(".vimrc")).unwrap();
+    write(&home.join(".vimrc"), "replaced");
+    uninstall(&get_args(&home, &["uninstall"])).unwrap();
+    let mut expected = before.to_owned();
+    expected.push((home.join(".vimrc"), "replaced".to_owned()));
+    expected.sort();
+    assert_eq!(snapshot(&home), expected);
+    assert!(kmanifest::Manifest::load().unwrap().installs.is_empty());
+    assert!(uninstall(&get_args(&home, &["uninstall"])).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn restore_replaces_links() {
+    let dir = kfs::get_test_dir("kot-restore");
+    let (dot, home) = (dir.join("dot"), dir.join("home"));
+    write(&dot.join(".bashrc"), "bashrc");
+    write(&dot.join(".profile"), "profile");
+    write(&dot.join(".vimrc"), "vimrc");
+    write(&home.join(".bashrc"), "bash conflict");
+    write(&home.join(".profile"), "profile conflict");
+    install_or_rollback(&mut get_args(&home, &["--force", dot.to_str().unwrap()])).unwrap();
+    let backup_dir = kfs::get_backup_root().unwrap().join("dot:2021-01-01T00:00:00");
+    assert_eq!(snapshot(&backup_dir), [
+      (backup_dir.join(".bashrc"), "bash conflict".to_owned()),
+      (backup_dir.join(".profile"), "profile conflict".to_owned()),
+    ]);
+
+    // Backups are referred to by name, and only the configurations provided are restored
+    let backup = PathBuf::from("dot:2021-01-01T00:00:00");
+    restore(&get_args(&home, &["restore"]), Some(&backup), &vec![PathBuf::from(".profile")]).unwrap();
+    assert_eq!(std::fs::read_to_string(home.join(".profile")).unwrap(), "profile conflict");
+    assert!(kfs::is_link_to(&home.join(".bashrc"), &dot.join(".bashrc")));
+    assert!(restore(&get_args(&home, &["restore"]), Some(&backup), &vec![PathBuf::from(".profile")]).is_err());
+
+    // Restoring the rest of the backup removes it, and the links it replaced are no longer recorded
+    restore(&get_args(&home, &["restore"]), Some(&backup_dir), &vec![]).unwrap();
+    assert_eq!(snapshot(&home), [
+      (home.join(".bashrc"), "bash conflict".to_owned()),
+      (home.join(".profile"), "profile conflict".to_owned()),
+      (home.join(".vimrc"), format!("-> {:?}", dot.join(".vimrc"))),
+    ]);
+    assert!(!backup_dir.exists());
+    let manifest = kmanifest::Manifest::load().unwrap();
+    let targets: Vec<_> = manifest.installs[0].links.iter().map(|link| link.target.to_owned()).collect();
+    assert_eq!(targets, [home.join(".vimrc")]);
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn status_of_each_config() {
+    let dir = kfs::get_test_dir("kot-status");
+    let (dot, home) = (dir.join("dot"), dir.join("home"));
+    write(&dot.join(".bashrc"), "bashrc");
+    write(&dot.join(".vimrc"), "vimrc");
+    write(&home.join(".bashrc"), "replaced");
+    let mode = kfs::InstallMode::Symlink;
+    std::os::unix::fs::symlink(dot.join(".vimrc"), home.join(".vimrc")).unwrap();
+    std::os::unix::fs::symlink(dot.join(".profile"), home.join(".profile")).unwrap();
+    assert_eq!(check_status(&dot.join(".vimrc"), &home.join(".vimrc"), mode), ConfigStatus::Installed);
+    assert_eq!(check_status(&dot.join(".bashrc"), &home.join(".bashrc"), mode), ConfigStatus::Replaced);
+    assert_eq!(check_status(&dot.join(".profile"), &home.join(".profile"), mode), ConfigStatus::Broken);
+    assert_eq!(check_status(&dot.join(".zshrc"), &home.join(".zshrc"), mode), ConfigStatus::NotInstalled);
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  /// Creates dotfiles and an install directory containing a conflict and a link from a previous installation
+  /// + Returns a journal installing the dotfiles, with one of each action that is reversed by a rollback
+  fn get_journal(dir: &Path) -> kjournal::Journal {
+    let (dot, home) = (dir.join("dot"), dir.join("home"));
+    write(&dot.join(".bashrc"), "bashrc");
+    write(&dot.join(".profile"), "profile");
+    write(&dot.join(".config/nvim/init.vim"), "init");
+    write(&home.join(".bashrc"), "conflict");
+    write(&home.join(".local/share/history"), "history");
+    std::os::unix::fs::symlink(dot.join(".profile"), home.join(".profile")).unwrap();
+    let mode = kfs::InstallMode::Symlink;
+    return kjournal::Journal {
+      name: "dot".to_owned(),
+      package: None,
+      dotfiles: dot.to_owned(),
+      source: dot.to_owned(),
+      commit: None,
+      install_dir: home.to_owned(),
+      backup_dir: dir.join("backup"),
+      timestamp: String::new(),
+      step: 0,
+      actions: vec![
+        kplan::Action::Remove { config: dot.join(".profile"), target: home.join(".profile"), mode, backup: None },
+        kplan::Action::Backup { target: home.join(".bashrc"), backup: dir.join("backup/.bashrc") },
+        kplan::Action::Link { config: dot.join(".bashrc"), target: home.join(".bashrc"), mode },
+        kplan::Action::Mkdir { path: home.join(".config") },
+        kplan::Action::Link { config: dot.join(".config/nvim"), target: home.join(".config/nvim"), mode },
+      ],
+    };
+  }
+
+  #[test]
+  fn rollback_after_each_step() {
+    let dir = kfs::get_test_dir("kot-rollback");
+    let steps = get_journal(&dir).actions.len();
+    // Installations are interrupted before or after applying the action at step, or fail once every action is applied
+    for step in 0..=steps {
+      for interrupted in [false, true] {
+        let dir = kfs::get_test_dir("kot-rollback");
+        let mut journal = get_journal(&dir);
+        let before = snapshot(&dir);
+        let applied = match interrupted {
+          true => std::cmp::min(step + 1, steps),
+          false => step,
+        };
+        for action in journal.actions[..applied].iter() {
+          apply_action(action).unwrap();
+        }
+        journal.step = step;
+        rollback_install(&journal).unwrap();
+        assert_eq!(snapshot(&dir), before, "Rolled back after step {}, interrupted: {}", step, interrupted);
+      }
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn failing_hooks_roll_back() {
+    let dir = kfs::get_test_dir("kot-hooks");
+    let (dot, home) = (dir.join("dot"), dir.join("home"));
+    write(&dot.join(".bashrc"), "bashrc");
+    write(&home.join(".bashrc"), "bash conflict");
+    let before = snapshot(&home);
+    // Hooks are run from the dotfiles, so each hook records that it ran within dir
+    for hook in ["pre-install", "post-install"] {
+      write(&dot.join(krepo::KOT_TOML), &format!("[hooks]\n{} = \"touch ../{} && exit 1\"\n", hook, hook));
+      let error = install_or_rollback(&mut get_args(&home, &["--force", dot.to_str().unwrap()])).unwrap_err();
+      assert!(matches!(error.kind, ErrorKind::HookError(_)), "{}", error);
+      assert!(dir.join(hook).exists());
+      assert_eq!(snapshot(&home), before, "Rolled back after failing {} hook", hook);
+      assert!(kjournal::Journal::load().unwrap().is_none());
+      assert!(kmanifest::Manifest::load().unwrap().installs.is_empty());
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn uninstall_hooks_of_installed_package() {
+    let dir = kfs::get_test_dir("kot-uninstall-hooks");
+    let (dot, home) = (dir.join("dot"), dir.join("home"));
+    write(&dot.join(".bashrc"), "bashrc");
+    write(&dot.join(krepo::KOT_TOML), "[hooks]\npre-uninstall = \"echo $KOT_HOOK >> ../hooks\"\n");
+    install_or_rollback(&mut get_args(&home, &["--force", dot.to_str().unwrap()])).unwrap();
+    uninstall(&get_args(&home, &["uninstall"])).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.join("hooks")).unwrap(), "pre-uninstall\n");
+
+    // Hooks of packages are not run for dotfiles that were installed before they listed packages
+    install_or_rollback(&mut get_args(&home, &["--force", dot.to_str().unwrap()])).unwrap();
+    write(&dot.join(krepo::KOT_TOML), concat!(
+      "[packages.shell]\ndir = \".\"\n",
+      "hooks = { pre-uninstall = \"echo shell >> ../hooks\" }\n",
+    ));
+    uninstall(&get_args(&home, &["uninstall"])).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.join("hooks")).unwrap(), "pre-uninstall\n");
+    assert!(home.join(".bashrc").symlink_metadata().is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  /// Runs git within dir as a test user, panicking if it fails
+  fn git(dir: &Path, arguments: &[&str]) {
+    let output = std::process::Command::new("git").arg("-C").arg(dir)
+        .args(["-c", "user.name=kot", "-c", "user.email=kot@localhost"]).args(arguments)
+        .output().unwrap();
+    assert!(output.status.success(), "git {:?}: {}", arguments, String::from_utf8_lossy(&output.stderr));
+  }
+
+  #[test]
+  fn update_plans_clone_again() {
+    let dir = kfs::get_test_dir("kot-update");
+    let (work, home, clone) = (dir.join("work"), dir.join("home"), dir.join("clone"));
+    write(&work.join(".bashrc"), "bashrc");
+    write(&work.join(".vimrc"), "vimrc");
+    write(&work.join(krepo::KOT_TOML), "[hooks]\npre-install = \"exit 1\"\n");
+    kfs::create_dir_all(&home).unwrap();
+    git(&work, &["init", "--quiet"]);
+    git(&work, &["add", "."]);
+    git(&work, &["commit", "--quiet", "-m", "Add bash and vim"]);
+    let repo_url = format!("file://{}", work.display());
+    let install = || {
+      let mut args = get_args(&home, &["--clone-dir", clone.to_str().unwrap(), &repo_url]);
+      args.is_repo = true;
+      return handle_args(&mut args);
+    };
+
+    // Clones are removed when the first installation fails, so the repository can be installed again
+    assert!(install().is_err());
+    assert!(!clone.exists());
+    git(&work, &["rm", "--quiet", krepo::KOT_TOML]);
+    git(&work, &["commit", "--quiet", "-m", "Remove hooks"]);
+    install().unwrap();
+    assert!(kfs::is_link_to(&home.join(".vimrc"), &clone.join(".vimrc")));
+
+    // Clones with local modifications are only updated with --allow-modified, even if --force is set
+    git(&work, &["rm", "--quiet", ".vimrc"]);
+    write(&work.join(".zshrc"), "zshrc");
+    git(&work, &["add", ".zshrc"]);
+    git(&work, &["commit", "--quiet", "-m", "Replace vim with zsh"]);
+    std::fs::write(clone.join(".bashrc"), "modified").unwrap();
+    assert!(handle_args(&mut get_args(&home, &["--force", "update"])).is_err());
+    assert!(clone.join(".vimrc").exists());
+    handle_args(&mut get_args(&home, &["update", "--allow-modified"])).unwrap();
+
+    // Configurations are planned again, so removed configurations are unlinked and new configurations are linked
+    assert!(home.join(".vimrc").symlink_metadata().is_err());
+    assert!(kfs::is_link_to(&home.join(".zshrc"), &clone.join(".zshrc")));
+    assert_eq!(std::fs::read_to_string(home.join(".bashrc")).unwrap(), "modified");
+    let manifest = kmanifest::Manifest::load().unwrap();
+    assert_eq!(manifest.installs.len(), 1);
+    assert_eq!(manifest.installs[0].commit, Some(kgit::get_commit(&work).unwrap()));
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn adopt_moves_configs_into_dotfiles() {
+    let dir = kfs::get_test_dir("kot-adopt");
+    let (dot, home) = (dir.join("dot"), dir.join("home"));
+    write(&dot.join(".bashrc"), "bashrc");
+    write(&home.join(".vimrc"), "vimrc");
+    write(&home.join(".config/git/config"), "git");
+    write(&dir.join("outside"), "outside");
+    install_or_rollback(&mut get_args(&home, &["--force", dot.to_str().unwrap()])).unwrap();
+    let get_error = |files: &[PathBuf]| {
+      return match adopt(&get_args(&home, &[]), files, false).unwrap_err().kind {
+        ErrorKind::ConfigError(message) => message,
+        kind => panic!("Unexpected error {:?}", kind),
+      };
+    };
+
+    // Every configuration is checked before any is moved
+    let before = (snapshot(&dot), snapshot(&home));
+    assert!(get_error(&[home.join(".vimrc"), home.join(".bashrc")]).contains("is already installed from"));
+    assert!(get_error(&[home.join(".vimrc"), dir.join("outside")]).contains("is not within"));
+    assert_eq!((snapshot(&dot), snapshot(&home)), before);
+
+    adopt(&get_args(&home, &[]), &[home.join(".vimrc"), home.join(".config/git")], false).unwrap();
+    assert_eq!(std::fs::read_to_string(dot.join(".vimrc")).unwrap(), "vimrc");
+    assert_eq!(std::fs::read_to_string(dot.join(".config/git/config")).unwrap(), "git");
+    assert!(kfs::is_link_to(&home.join(".vimrc"), &dot.join(".vimrc")));
+    assert!(kfs::is_link_to(&home.join(".config/git"), &dot.join(".config/git")));
+    let manifest = kmanifest::Manifest::load().unwrap();
+    let targets: Vec<_> = manifest.installs[0].links.iter().map(|link| link.target.to_owned()).collect();
+    assert_eq!(targets, [home.join(".bashrc"), home.join(".vimrc"), home.join(".config/git")]);
+
+    // Adopted configurations are reversed by uninstall, and are kept within the dotfiles
+    uninstall(&get_args(&home, &["uninstall"])).unwrap();
+    assert!(home.join(".vimrc").symlink_metadata().is_err());
+    assert!(dot.join(".vimrc").is_file());
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn restore_outside_install_dir() {
+    let dir = kfs::get_test_dir("kot-restore-outside");
+    let (dot, home) = (dir.join("dot"), dir.join("home"));
+    write(&dot.join(krepo::KOT_TOML), &format!("targets = {{ \"app.conf\" = {:?} }}", dir.join("etc/app.conf")));
+    write(&dot.join("app.conf"), "app");
+    write(&dot.join(".bashrc"), "bashrc");
+    write(&dir.join("etc/app.conf"), "app conflict");
+    write(&home.join(".bashrc"), "bash conflict");
+    install_or_rollback(&mut get_args(&home, &["--force", dot.to_str().unwrap()])).unwrap();
+    assert!(kfs::is_link_to(&dir.join("etc/app.conf"), &dot.join("app.conf")));
+
+    // Backups of targets outside of the install directory are restored to the target they were backed up from
+    let backup = PathBuf::from("dot:2021-01-01T00:00:00");
+    restore(&get_args(&home, &["restore"]), Some(&backup), &vec![]).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.join("etc/app.conf")).unwrap(), "app conflict");
+    assert_eq!(snapshot(&home), [(home.join(".bashrc"), "bash conflict".to_owned())]);
+    assert!(!kfs::get_backup_root().unwrap().join(&backup).exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn packages_are_backed_up_separately() {
+    let dir = kfs::get_test_dir("kot-packages");
+    let (dot, home) = (dir.join("dot"), dir.join("home"));
+    write(&dot.join(krepo::KOT_TOML), "[packages.vim]\n[packages.shell]\ndir = \"bash\"");
+    write(&dot.join("vim/.vimrc"), "vimrc");
+    write(&dot.join("bash/.bashrc"), "bashrc");
+    write(&dot.join(".profile"), "profile");
+    write(&home.join(".vimrc"), "vim conflict");
+    write(&home.join(".bashrc"), "bash conflict");
+    let backup_dir = dir.join("backups");
+    let mut args = get_args(&home, &["--force", "-b", backup_dir.to_str().unwrap(), dot.to_str().unwrap()]);
+    install_or_rollback(&mut args).unwrap();
+    // Only configurations within packages are installed
+    assert_eq!(snapshot(&home), [
+      (home.join(".bashrc"), format!("-> {:?}", dot.join("bash/.bashrc"))),
+      (home.join(".vimrc"), format!("-> {:?}", dot.join("vim/.vimrc"))),
+    ]);
+    assert_eq!(snapshot(&dir.join("backups.vim")), [(dir.join("backups.vim/.vimrc"), "vim conflict".to_owned())]);
+    let packages: Vec<_> = kmanifest::Manifest::load().unwrap().installs.into_iter()
+        .map(|install| (install.package.unwrap(), install.backup_dir)).collect();
+    assert_eq!(packages, [
+      ("shell".to_owned(), dir.join("backups.shell")),
+      ("vim".to_owned(), dir.join("backups.vim")),
+    ]);
+
+    // The backup of a package mirrors the install directory
+    restore(&get_args(&home, &["restore"]), Some(&dir.join("backups.vim")), &vec![]).unwrap();
+    assert_eq!(std::fs::read_to_string(home.join(".vimrc")).unwrap(), "vim conflict");
+    uninstall(&get_args(&home, &["-p", "shell", "uninstall"])).unwrap();
+    assert_eq!(std::fs::read_to_string(home.join(".bashrc")).unwrap(), "bash conflict");
+    assert!(kmanifest::Manifest::load().unwrap().installs.is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -8,7 +8,6 @@
 
 use std::borrow::Borrow;
 use std::path::{Path, PathBuf};
-use regex::Regex;
 use structopt::StructOpt;
 use crate::kot::kerror::{Error, ErrorKind};
 use crate::kot::err;
@@ -16,6 +15,9 @@ use crate::kot::kfs::create_dir_all;
 
 use chrono;
 use super::kfs;
+use super::kconfig;
+use super::kgit;
+use super::krepo;
 
 // =============================================================================
 // STRUCTS
@@ -32,27 +34,36 @@ pub struct Cli {
   /// System path or repository URL for dotfiles we want to install.
   /// If a path is used, it can either be local to CWD or absolute.
   /// If a URL is used for a dotfiles repository, the repo is cloned into $HOME/.local/shared/kot/dotfiles/
-  #[structopt(parse(from_os_str))]
-  pub dotfiles: PathBuf,
+  /// Repositories can be https, http, ssh, git, or file URLs, scp-like sources such as git@gitlab.com:user/dot,
+  /// local bare repositories, or shorthands such as gh:user/dot and gl:user/dot.
+  ///
+  /// Required unless a subcommand is used, which instead accepts its own dotfiles argument.
+  /// Can also be set within the kot configuration file.
+  #[structopt(
+  env = "KOT_DOTFILES",
+  parse(from_os_str)
+  )]
+  pub dotfiles: Option<PathBuf>,
 
   /// The location to attempt installation of user configurations
   ///
   /// The desired installation directory for user configurations.
   /// By default this is your $HOME directory
-  /// This could optionally point to some other directory to perform a dry run, or the --dry-run flag could be set
+  /// This could optionally point to some other directory for testing, or the --dry-run flag could be set
   #[structopt(
-  env = "HOME", // Default value to env variable $HOME
+  env = "KOT_INSTALL_DIR",
   name = "install",
   short, long,
   parse(from_os_str)
   )]
-  pub install_dir: PathBuf,
+  pub install: Option<PathBuf>,
 
   /// The location to store backups for this user
   ///
   /// If no backup-dir is provided, we create one within the default kot data directory:
   /// $HOME/.local/share/kot/backups/
   #[structopt(
+  env = "KOT_BACKUP_DIR",
   name = "backup-dir",
   short, long,
   parse(from_os_str)
@@ -68,6 +79,7 @@ pub struct Cli {
   /// We will clone into $HOME/clonedir, and NOT $HOME/clonedir/Dotfiles
   /// The default path for cloned repos is $HOME/.local/share/kot/dotfiles/
   #[structopt(
+  env = "KOT_CLONE_DIR",
   name = "clone-dir",
   short, long,
   parse(from_os_str)
@@ -84,25 +96,309 @@ pub struct Cli {
   )]
   pub force: bool,
 
-  /// Installs configurations to $HOME/.local/shared/kot/dry-runs
+  /// Prints the actions an installation would perform without performing them
   ///
   /// Useful flag to set when testing what an install would do to your home directory.
-  /// This is synonymous with setting --install $HOME/.local/shared/kot/dry-runs/$USER.
-  /// Subsequent runs with this flag set will not delete the contents of this directory.
+  /// Each link, backup, skip, and removal is planned against the real install directory and printed.
+  /// No files or directories are created, moved, or removed, and the installation is not recorded.
   #[structopt(
   name = "dry-run",
   short, long
   )]
   pub dry_run: bool,
 
+  /// The method used to install configurations
+  ///
+  /// One of symlink, relative-symlink, hardlink, or copy. By default configurations are installed as symbolic links.
+  /// Hard links require the dotfiles and install directory to be on the same filesystem.
+  /// Directories installed using hardlink or copy are created within the install directory.
+  #[structopt(
+  env = "KOT_MODE",
+  name = "mode",
+  short, long
+  )]
+  pub mode: Option<kfs::InstallMode>,
+
+  /// Installs a single configuration using a different mode, provided as <CONFIG>=<MODE>
+  ///
+  /// CONFIG is the path to a file or directory relative to the root of the dotfiles.
+  /// The mode applies to the configuration and everything within it, and can be provided multiple times.
+  /// For example, --file-mode .ssh/config=copy
+  #[structopt(
+  name = "file-mode",
+  long,
+  number_of_values = 1,
+  parse(try_from_str = parse_file_mode)
+  )]
+  pub file_modes: Vec<(PathBuf, kfs::InstallMode)>,
+
+  /// Creates symbolic links using the path to each configuration relative to the link
+  ///
+  /// Relative links continue to work when the install directory and dotfiles are moved together,
+  /// such as restoring a home directory onto a machine with a different username.
+  /// Configurations installed as symlink by --mode or --file-mode are installed as relative-symlink instead.
+  #[structopt(
+  name = "relative",
+  short, long
+  )]
+  pub relative: bool,
+
+  /// Installs files and directories named with a dot- prefix using a leading dot instead
+  ///
+  /// The prefix is translated at every level within the dotfiles, so dot-config/nvim is installed as .config/nvim.
+  /// This matches the --dotfiles option of GNU stow, so stow dotfiles can be installed without renaming them.
+  #[structopt(
+  name = "dot-prefix",
+  long
+  )]
+  pub dot_prefix: bool,
+
+  /// Installs a single package from the dotfiles, and can be provided multiple times
+  ///
+  /// Packages are listed within kot.toml at the root of the dotfiles, and are usually subdirectories of the dotfiles.
+  /// If no packages are provided, every package listed within kot.toml is installed,
+  /// or the dotfiles are installed as a whole if kot.toml does not list any packages.
+  /// Also selects the packages to uninstall or check the status of.
+  #[structopt(
+  name = "package",
+  short, long,
+  number_of_values = 1
+  )]
+  pub packages: Vec<String>,
+
+  /// Installs the overlay for a profile on top of the dotfiles, and can be provided multiple times
+  ///
+  /// Each profile is a directory within the profiles directory listed in kot.toml that mirrors the layout of the dotfiles.
+  /// Files within a profile shadow files at the same path within the dotfiles, and later profiles shadow earlier ones.
+  /// If no profiles are provided, the profile named after the hostname is installed if the dotfiles contain one.
+  #[structopt(
+  name = "profile",
+  short = "P", long,
+  number_of_values = 1
+  )]
+  pub profiles: Vec<String>,
+
+  /// Installs VCS metadata and repository files found at the root of the dotfiles
+  ///
+  /// By default .git, .gitignore, .gitmodules, README*, LICENSE*, and similar files at the root of the dotfiles
+  /// are not installed, since they describe the dotfiles repository and not the user configurations within it.
+  #[structopt(
+  name = "include-repo-files",
+  long
+  )]
+  pub include_repo_files: bool,
+
+  /// A branch or tag of the dotfiles repository to clone
+  ///
+  /// By default the default branch of the repository is cloned. Only used when dotfiles is a repository URL.
+  #[structopt(
+  name = "branch",
+  long
+  )]
+  pub branch: Option<String>,
+
+  /// A commit, branch, or tag of the dotfiles repository to check out after it is cloned
+  ///
+  /// The revision is checked out as a detached HEAD, and submodules are updated to match it.
+  /// Only used when dotfiles is a repository URL.
+  #[structopt(
+  name = "rev",
+  long
+  )]
+  pub rev: Option<String>,
+
+  /// Completes or rolls back an interrupted installation without prompting
+  ///
+  /// One of complete or rollback. If kot was interrupted while installing dotfiles, the next run finds the journal
+  /// of the installation and prompts to complete it or roll it back. If --force is set without --recover,
+  /// the interrupted installation is rolled back.
+  #[structopt(
+  name = "recover",
+  long
+  )]
+  pub recover: Option<Recover>,
+
+  /// Skips the hooks listed within kot.toml at the root of the dotfiles
+  ///
+  /// By default the pre-install, post-install, pre-uninstall, and post-uninstall hooks of each package are run.
+  /// A failing pre-install or post-install hook aborts the installation and rolls back any changes.
+  #[structopt(
+  name = "no-hooks",
+  long
+  )]
+  pub no_hooks: bool,
+
+  // Absolute path to the install directory; Not used by CLI directly
+  // + Initialized from --install, configuration files, or $HOME, in that order
+  #[structopt(skip)]
+  pub install_dir: PathBuf,
+
   // Indicates if dotfiles is a git repository URL; Not used by CLI directly
   // + Initialized with result of regex pattern matching
   #[structopt(skip)]
   pub is_repo: bool,
 
-  // Not used by CLI, used to uninstall dotfiles when error is hit
+  // Not used by CLI, commit checked out within clone_dir when dotfiles is a git repository URL
+  // + Recorded by the install manifest, see kgit::get_commit()
+  #[structopt(skip)]
+  pub commit: Option<String>,
+
+  // Not used by CLI, local time this run was started
+  // + Shared by the default backup_dir name and the install manifest record
+  #[structopt(skip)]
+  pub timestamp: String,
+
+  // Not used by CLI, variables provided to templates by configuration files
+  // + Initialized from [vars] within the user configuration and .kotrc, see ktemplate::get_vars()
   #[structopt(skip)]
-  pub conflicts: Vec<PathBuf>,
+  pub vars: std::collections::HashMap<String, String>,
+
+  #[structopt(subcommand)]
+  pub command: Option<Command>,
+}
+
+/// Choices for recovering an installation that was interrupted, see --recover
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Recover {
+  /// Applies the remaining steps of the installation and records it
+  Complete,
+  /// Reverses each step of the installation that was applied
+  Rollback,
+}
+
+impl std::str::FromStr for Recover {
+  type Err = String;
+
+  fn from_str(recover: &str) -> Result<Self, Self::Err> {
+    return match recover {
+      "complete" => Ok(Recover::Complete),
+      "rollback" => Ok(Recover::Rollback),
+      _ => Err(format!("Unknown recovery {:?}; Expected complete or rollback", recover)),
+    };
+  }
+}
+
+// Subcommands for managing dotfiles that were previously installed
+#[derive(Debug, StructOpt)]
+pub enum Command {
+  /// Reverses a previous installation of dotfiles
+  ///
+  /// Removes the links created by the most recent installation of the dotfiles into the install directory,
+  /// and moves configurations stored in the backup directory for that installation back into place.
+  /// Links that were replaced since they were installed are left untouched.
+  /// Every installed package is uninstalled, unless packages are selected by --package.
+  Uninstall {
+    /// Name, path, or repository URL of the installed dotfiles
+    ///
+    /// If no dotfiles are provided, the most recent installation into the install directory is reversed.
+    #[structopt(parse(from_os_str))]
+    dotfiles: Option<PathBuf>,
+  },
+
+  /// Restores configurations from a backup into the install directory
+  ///
+  /// Backups are created within $HOME/.local/share/kot/backups/ when installing dotfiles that conflict
+  /// with existing configurations. If no backup is provided, the available backups are listed instead.
+  /// Links created by kot that occupy the location of a backed up configuration are removed before restoring it.
+  /// Configurations that were modified since installation are left untouched.
+  Restore {
+    /// Name of a backup within $HOME/.local/share/kot/backups/, or a path to a backup directory
+    #[structopt(parse(from_os_str))]
+    backup: Option<PathBuf>,
+
+    /// Configurations within the backup to restore, relative to the backup directory
+    ///
+    /// If no configurations are provided, the entire backup is restored.
+    #[structopt(parse(from_os_str))]
+    files: Vec<PathBuf>,
+  },
+
+  /// Reports the state of each configuration within the install directory
+  ///
+  /// Each configuration within the dotfiles is reported as installed if it is linked by kot,
+  /// replaced if another file exists at its location, broken if the link no longer resolves,
+  /// or not installed if nothing exists at its location.
+  Status {
+    /// Name, path, or repository URL of the dotfiles to check
+    ///
+    /// If no dotfiles are provided, the most recent installation into the install directory is checked.
+    #[structopt(parse(from_os_str))]
+    dotfiles: Option<PathBuf>,
+  },
+
+  /// Lists the packages within dotfiles
+  ///
+  /// Packages are listed within kot.toml at the root of the dotfiles.
+  /// Each package is reported as installed if it was installed into the install directory.
+  /// Dotfiles that do not list any packages are reported as a whole.
+  List {
+    /// Name or path of the dotfiles to list packages from
+    ///
+    /// If no dotfiles are provided, the dotfiles most recently installed into the install directory are used.
+    #[structopt(parse(from_os_str))]
+    dotfiles: Option<PathBuf>,
+  },
+
+  /// Updates the clone of a dotfiles repository and installs it again
+  ///
+  /// Fetches and fast-forwards the branch checked out within the clone, and updates its submodules.
+  /// Clones with local modifications are not updated unless --allow-modified is set.
+  /// The packages that were installed are planned again, so new configurations are linked
+  /// and configurations that were removed from the dotfiles are unlinked.
+  Update {
+    /// Name or repository URL of the installed dotfiles
+    ///
+    /// If no dotfiles are provided, the dotfiles most recently installed into the install directory are updated.
+    #[structopt(parse(from_os_str))]
+    dotfiles: Option<PathBuf>,
+
+    /// Updates the clone even if it contains uncommitted changes or untracked files
+    ///
+    /// Local changes are kept, so the update fails if the fast-forward would overwrite them.
+    #[structopt(long)]
+    allow_modified: bool,
+  },
+
+  /// Moves configurations from the install directory into the dotfiles, and installs them in their place
+  ///
+  /// Each configuration is moved to the path within the dotfiles it would be installed from, using the targets
+  /// listed within kot.toml and --dot-prefix, and is replaced by a link to its new location.
+  /// Adopted configurations are recorded in the manifest, so they are reversed by uninstall.
+  /// When the dotfiles list packages, the package to move configurations into is selected by --package.
+  Adopt {
+    /// Configurations within the install directory to move into the dotfiles
+    #[structopt(parse(from_os_str), required = true)]
+    files: Vec<PathBuf>,
+
+    /// Name, path, or repository URL of the dotfiles to move configurations into
+    ///
+    /// If no dotfiles are provided, the dotfiles most recently installed into the install directory are used.
+    #[structopt(long, parse(from_os_str))]
+    dotfiles: Option<PathBuf>,
+
+    /// Commits the adopted configurations within the dotfiles repository
+    ///
+    /// Any other changes already staged within the repository are committed along with them.
+    #[structopt(long)]
+    commit: bool,
+  },
+
+  /// Encrypts a file so it can be stored within dotfiles as a secret
+  ///
+  /// Secrets are encrypted with a passphrase using age, and end with .age so they are decrypted when installed.
+  /// The passphrase is read from $KOT_PASSPHRASE if it is set, otherwise it is prompted for.
+  /// Existing secrets are only replaced if --force is set.
+  Encrypt {
+    /// File containing the configuration to encrypt
+    #[structopt(parse(from_os_str))]
+    file: PathBuf,
+
+    /// Path to write the secret to, such as a path within the dotfiles ending with .age
+    ///
+    /// If no path is provided, the secret is written next to the file with .age appended to its name.
+    #[structopt(parse(from_os_str))]
+    output: Option<PathBuf>,
+  },
 }
 
 // =============================================================================
@@ -119,75 +415,367 @@ pub fn from_args() -> super::Result<Cli> {
   s.normalize()
 }
 
+/// Checks if dotfiles provided to the CLI are a git repository, see kgit::get_repo_url()
+pub fn is_repo_url(dotfiles: &Path) -> super::Result<bool> {
+  return Ok(kgit::get_repo_url(dotfiles)?.is_some());
+}
+
+/// Parses a <CONFIG>=<MODE> pair provided to the --file-mode option
+fn parse_file_mode(arg: &str) -> Result<(PathBuf, kfs::InstallMode), String> {
+  return match arg.rsplit_once('=') {
+    Some((config, mode)) => Ok((PathBuf::from(config), mode.parse()?)),
+    None => Err(format!("Expected <CONFIG>=<MODE>, found {:?}", arg)),
+  };
+}
+
 impl Cli {
+  /// Returns the install mode for a configuration within the dotfiles
+  /// + The most specific --file-mode containing the configuration is used, otherwise --mode is used
+  /// + If --relative is set, symbolic links are created using relative paths
+  pub fn get_mode(&self, dotfiles: &Path, package: &krepo::Package, config: &Path) -> kfs::InstallMode {
+    // Configurations within a profile use the modes provided for the path they shadow
+    let relative: PathBuf = match package.profiles.as_ref().and_then(|profiles| config.strip_prefix(profiles).ok()) {
+      Some(profile) => profile.components().skip(1).collect(),
+      None => config.strip_prefix(dotfiles).unwrap_or(config).to_owned(),
+    };
+    let mode = self.file_modes.iter()
+        .filter(|(path, _)| relative.starts_with(path))
+        .max_by_key(|(path, _)| path.components().count())
+        .map_or(self.mode.unwrap_or_default(), |(_, mode)| *mode);
+    return match mode {
+      kfs::InstallMode::Symlink if self.relative => kfs::InstallMode::RelativeSymlink,
+      _ => mode,
+    };
+  }
+
+  /// Returns the name of the dotfiles provided to the CLI; Used to name backups and manifest records
+  /// + System paths are canonicalized first, so dotfiles such as . are named after the directory they refer to
+  pub fn get_name(&self) -> super::Result<String> {
+    let dotfiles = match &self.dotfiles {
+      Some(dotfiles) => dotfiles.canonicalize().unwrap_or(dotfiles.to_owned()),
+      None => err!(
+        ErrorKind::ConfigError("No dotfiles provided".to_owned()),
+        "Provide the dotfiles to install".to_owned()
+      ),
+    };
+    return match dotfiles.file_name() {
+      Some(name) => Ok(name.to_string_lossy().to_string()),
+      None => err!(
+        ErrorKind::ConfigError(format!("Unable to name dotfiles {:?}", dotfiles)),
+        "Provide a directory or repository URL ending in the name of the dotfiles".to_owned()
+      ),
+    };
+  }
+
+  /// Returns the profiles to install on top of the dotfiles, see kfs::Layer
+  /// + profiles_dir is the directory listed within kot.toml, see krepo::Repo::get_profiles_dir()
+  /// + If no profiles were provided, the profile named after the hostname is used if the dotfiles contain one
+  /// + Each profile that was provided must exist within the dotfiles
+  pub fn get_profiles(&self, profiles_dir: Option<&PathBuf>) -> super::Result<Vec<String>> {
+    let profiles_dir = match profiles_dir {
+      Some(profiles_dir) => profiles_dir,
+      None if self.profiles.is_empty() => return Ok(vec![]),
+      None => err!(
+        ErrorKind::ConfigError(format!("No profile {:?} within the dotfiles", self.profiles[0])),
+        format!("List the directory containing profiles within {}, such as profiles = \"profiles\"", krepo::KOT_TOML)
+      ),
+    };
+    if self.profiles.is_empty() {
+      return Ok(kfs::get_hostname().into_iter()
+          .filter(|hostname| profiles_dir.join(hostname).is_dir())
+          .collect());
+    }
+    for profile in self.profiles.iter() {
+      let mut components = Path::new(profile).components();
+      let valid = matches!(components.next(), Some(std::path::Component::Normal(_)))
+          && components.next().is_none();
+      if !valid || !profiles_dir.join(profile).is_dir() {
+        err!(
+          ErrorKind::ConfigError(format!("No profile {:?} within {:?}", profile, profiles_dir)),
+          "Profiles are subdirectories of the profiles directory within the dotfiles".to_owned()
+        );
+      }
+    }
+    return Ok(self.profiles.to_owned());
+  }
+
+  /// Returns the backup directory used while installing a package
+  /// + If the CLI was not provided a backup_dir, use default naming convention <NAME>[.<PACKAGE>]:<DATE>
+  /// + If the CLI was provided a backup_dir, each package is backed up next to it within <BACKUP_DIR>.<PACKAGE>,
+  ///     so each backup mirrors the install directory and can be restored on its own
+  pub fn get_backup_dir(&self, package: Option<&String>) -> super::Result<PathBuf> {
+    return match (&self.backup_dir, package) {
+      (Some(dir), None) => Ok(dir.to_owned()),
+      (Some(dir), Some(package)) => {
+        let mut backup_dir = dir.components().collect::<PathBuf>().into_os_string();
+        backup_dir.push(".".to_owned() + package);
+        Ok(PathBuf::from(backup_dir))
+      },
+      (None, _) => {
+        let mut backup_dir = kfs::get_backup_root()?;
+        backup_dir.push(self.get_name()?
+            + &package.map_or("".to_owned(), |package| ".".to_owned() + package)
+            + ":" + &self.timestamp
+        );
+        Ok(backup_dir)
+      },
+    };
+  }
+
+  /// Creates a backup directory before moving conflicting configurations into it
+  /// + Verifies backup directory does not already contain backups unless --force is set
+  pub fn create_backup_dir(&self, backup_dir: &PathBuf) -> super::Result<()> {
+    kfs::create_dir_all(backup_dir)?;
+
+    //
+    // Check if the backup directory provided is empty
+
+    // If there are files and the --force flag is not set, warn and abort
+    if !self.force && kfs::dir_entries(backup_dir)? > 1 {
+      err!(
+        ErrorKind::ConfigError(format!("Backups already exist at: {:?}", backup_dir)),
+        "Set the --force flag to overwrite configurations stored here".to_owned()
+      );
+    }
+    // If the --force flag is set, stash backup files in /tmp/ and create new
+    kfs::stash_dir(backup_dir)?;
+    return Ok(());
+  }
+
+  /// Fills in arguments that were not provided to the CLI or environment using configuration files
+  /// + Precedence is CLI > environment > user configuration > dotfiles configuration
+  /// + User configuration is read from user_config, see kconfig::get_user_config_path()
+  /// + Dotfiles configuration is read from .kotrc at the root of the dotfiles, if they are on the system
+  /// + Flags can be enabled by configuration files, but not disabled
+  fn apply_config(&mut self, user_config: &PathBuf) -> super::Result<()> {
+    let mut config = kconfig::Config::load(user_config)?;
+    if self.command.is_none() {
+      self.dotfiles = self.dotfiles.take().or(config.dotfiles.take());
+    }
+
+    // Dotfiles that are a repository URL can only provide configuration once they are cloned
+    if let Some(dotfiles) = &self.dotfiles {
+      let dotfiles_dir = match kgit::get_repo_url(dotfiles)? {
+        Some(repo_url) => match self.clone_dir.to_owned().or(config.clone_dir.to_owned()) {
+          Some(clone_dir) => clone_dir,
+          None => kfs::get_repo_path(&repo_url)?,
+        },
+        None => dotfiles.to_owned(),
+      };
+      config = config.merge(kconfig::Config::load(&dotfiles_dir.join(kconfig::KOTRC))?);
+    }
+
+    self.install = self.install.take().or(config.install);
+    self.backup_dir = self.backup_dir.take().or(config.backup_dir);
+    self.clone_dir = self.clone_dir.take().or(config.clone_dir);
+    self.mode = self.mode.or(config.mode);
+    self.force |= config.force.unwrap_or(false);
+    self.dry_run |= config.dry_run.unwrap_or(false);
+    self.include_repo_files |= config.include_repo_files.unwrap_or(false);
+    self.relative |= config.relative.unwrap_or(false);
+    self.dot_prefix |= config.dot_prefix.unwrap_or(false);
+    self.no_hooks |= config.no_hooks.unwrap_or(false);
+    if self.packages.is_empty() {
+      self.packages = config.package;
+    }
+    if self.profiles.is_empty() {
+      self.profiles = config.profile;
+    }
+    self.vars = config.vars;
+    // Modes provided to the CLI are last, so they take precedence over configured modes for the same path
+    let mut file_modes: Vec<_> = config.file_mode.into_iter().collect();
+    file_modes.append(&mut self.file_modes);
+    self.file_modes = file_modes;
+    return Ok(());
+  }
+
+  /// Initializes the time this run was started, and the backup directory provided to the CLI
+  fn init_timestamp(&mut self) -> super::Result<()> {
+    self.timestamp = chrono::offset::Local::now()
+        .format("%Y-%m-%dT%H:%M:%S").to_string();
+    // If a backup_dir was given to CLI, use it instead of the default, see get_backup_dir()
+    if let Some(dir) = &self.backup_dir {
+      self.backup_dir = Some(std::env::current_dir()?.join(dir));
+    }
+    return Ok(());
+  }
+
   /// Helper function to normalize arguments passed to program
   /// + Checks if dotfiles path is a repository URL
   /// + If dotfiles path is not a repo URL, checks the path exists on the system
   /// + Verifies install directory exists
   /// + Verifies backup directory exists and does not already contain backups
   pub fn normalize(mut self) -> super::Result<Self> {
-    // Determine if the dotfiles were provided as a github repository URL
-    let re_git = Regex::new(
-      r"^(([A-Za-z0-9]+@|http(|s)://)|(http(|s)://[A-Za-z0-9]+@))([A-Za-z0-9.]+(:\d+)?)(?::|/)([\d/\w.-]+?)(\.git){1}$"
-    );
-    self.is_repo = re_git.unwrap().is_match(&self.dotfiles.to_str().unwrap());
+    // Fill in any arguments that were not provided from kot configuration files
+    self.apply_config(&kconfig::get_user_config_path()?)?;
+    self.install_dir = match &self.install {
+      Some(dir) => dir.to_owned(),
+      None => kfs::get_home_dir()?,
+    };
+
+    // Subcommands only inspect or reverse previous installations
+    // + Skip creating backup and clone directories used for installing dotfiles
+    match &self.command {
+      Some(Command::Uninstall { dotfiles }) => {
+        self.dotfiles = dotfiles.to_owned();
+        self.install_dir = kfs::abs(&self.install_dir)?;
+        return Ok(self);
+      },
+      Some(Command::Status { dotfiles }) => {
+        self.dotfiles = dotfiles.to_owned();
+        self.install_dir = kfs::abs(&self.install_dir)?;
+        return Ok(self);
+      },
+      Some(Command::List { dotfiles }) => {
+        self.dotfiles = dotfiles.to_owned();
+        self.install_dir = kfs::abs(&self.install_dir)?;
+        return Ok(self);
+      },
+      Some(Command::Update { dotfiles, .. }) => {
+        if self.branch.is_some() || self.rev.is_some() {
+          err!(
+            ErrorKind::ConfigError("--branch and --rev can not be used to update dotfiles".to_owned()),
+            "The branch checked out within the clone is updated".to_owned()
+          );
+        }
+        self.dotfiles = dotfiles.to_owned();
+        self.install_dir = kfs::abs(&self.install_dir)?;
+        self.init_timestamp()?;
+        return Ok(self);
+      },
+      Some(Command::Adopt { dotfiles, .. }) => {
+        self.dotfiles = dotfiles.to_owned();
+        self.install_dir = kfs::abs(&self.install_dir)?;
+        self.init_timestamp()?;
+        return Ok(self);
+      },
+      Some(Command::Restore { .. }) => {
+        self.install_dir = kfs::abs(&self.install_dir)?;
+        return Ok(self);
+      },
+      Some(Command::Encrypt { .. }) => return Ok(self),
+      None => { }
+    }
 
-    if self.is_repo {
+    let dotfiles = match &self.dotfiles {
+      Some(dotfiles) => dotfiles.to_owned(),
+      None => err!(
+        ErrorKind::ConfigError("No dotfiles provided".to_owned()),
+        "Provide dotfiles to install, or see --help for available subcommands".to_owned()
+      ),
+    };
+    // Determine if the dotfiles were provided as a repository URL, see kgit::get_repo_url()
+    let repo_url = kgit::get_repo_url(&dotfiles)?;
+    self.is_repo = repo_url.is_some();
+    if !self.is_repo && (self.branch.is_some() || self.rev.is_some()) {
+      err!(
+        ErrorKind::ConfigError("--branch and --rev can only be used with a repository URL".to_owned()),
+        format!("Dotfiles {:?} are not a repository URL", dotfiles)
+      );
+    }
+
+    if let Some(repo_url) = &repo_url {
+      // Local bare repositories are recorded by their absolute path, so they can be found from any directory
+      if dotfiles.is_dir() {
+        self.dotfiles = Some(kfs::abs(&dotfiles)?);
+      }
       // If the dotfiles were provided as a repository URL initialize clone_dir
       self.clone_dir = match &self.clone_dir {
+        // A dry run never creates the clone directory
+        Some(d) if self.dry_run => Some(kfs::abs(d).unwrap_or(d.to_owned())),
         Some(d) => {
           kfs::create_dir_all(d)?;
           Some(kfs::abs(d)?)
         },
-        None => Some(kfs::get_repo_path(self.dotfiles.to_str().unwrap()))
+        None => Some(kfs::get_repo_path(repo_url)?)
       };
     }
     else {
       // If the dotfiles were provided as a path, canonicalize it
-      self.dotfiles = kfs::abs(&self.dotfiles)?;
+      self.dotfiles = Some(kfs::abs(&dotfiles)?);
     }
 
-    //
-    // If either the install, backup, or clone dir does not exist, create them
+    self.init_timestamp()?;
 
+    // A dry run only plans the installation, so leave the filesystem untouched
     if self.dry_run {
-      self.install_dir = Path::new(
-        &(env!("HOME").to_owned() + &"/.local/share/kot/dry-runs/" + env!("USER"))
-      ).to_path_buf();
-    }
-    self.install_dir = kfs::create_dir_all(&self.install_dir)?;
-    // If the CLI was not provided a backup_dir, use default naming convention
-    match self.backup_dir {
-      None => {
-        let mut backup_dir = kfs::get_data_dir();
-        backup_dir.push("backups/");
-        backup_dir.push(self.dotfiles.file_name().unwrap().to_str().unwrap().to_owned()
-            + ":" + &*chrono::offset::Local::now()
-            .format("%Y-%m-%dT%H:%M:%S").to_string()
-        );
-        self.backup_dir = Some(kfs::create_dir_all(&backup_dir)?);
-      }
-      Some(dir) => {
-        // If a backup_dir was given to CLI, use it instead of default
-        self.backup_dir = Some(kfs::create_dir_all(&dir)?);
-      }
+      self.install_dir = kfs::abs(&self.install_dir).unwrap_or(self.install_dir.to_owned());
+      return Ok(self);
     }
 
-
     //
-    // Check if the backup directory provided is empty
-
-    // If there are files and the --force flag is not set, warn and abort
-    if !self.force && kfs::dir_entries(&self.backup_dir.as_ref().unwrap())? > 1 {
-      return err!(
-        ErrorKind::ConfigError(format!("Backups already exist at: {:?}", &self.backup_dir)),
-        "Set the --force flag to overwrite configurations stored here".to_owned()
-      );
-    }
-    // If the --force flag is set, stash backup files in /tmp/ and create new
-    kfs::stash_dir(&self.backup_dir.as_ref().unwrap())?;
+    // If the install dir does not exist, create it
+    // + Backup directories are created for each package that has conflicts, see create_backup_dir()
+    self.install_dir = kfs::abs(&kfs::create_dir_all(&self.install_dir)?)?;
 
     // Available CLI options pass initial checks; Return them to caller
     return Ok(self);
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn config_precedence() {
+    let dir = kfs::get_test_dir("kcli-config");
+    let (dot, user_config) = (dir.join("dot"), dir.join("config.toml"));
+    kfs::write(&dot.join(kconfig::KOTRC), r#"
+      install = "/kotrc"
+      backup-dir = "/kotrc/backups"
+      clone-dir = "/kotrc/dotfiles"
+      mode = "copy"
+      dry-run = true
+      [vars]
+      email = "kotrc@example.com"
+      editor = "vim"
+    "#);
+    kfs::write(&user_config, r#"
+      install = "/user"
+      backup-dir = "/user/backups"
+      mode = "hardlink"
+      [vars]
+      email = "user@example.com"
+    "#);
+    let get_args = |arguments: &[&str], env: Option<&str>| {
+      // Only this test reads KOT_INSTALL_DIR, since the other tests never call apply_config()
+      if let Some(install) = env {
+        std::env::set_var("KOT_INSTALL_DIR", install);
+      }
+      let mut args = Cli::from_iter([&["kot"], arguments, &[dot.to_str().unwrap()]].concat());
+      std::env::remove_var("KOT_INSTALL_DIR");
+      args.apply_config(&user_config).unwrap();
+      return args;
+    };
+
+    let args = get_args(&[], None);
+    assert_eq!(args.install, Some(PathBuf::from("/user")));
+    assert_eq!(args.backup_dir, Some(PathBuf::from("/user/backups")));
+    assert_eq!(args.clone_dir, Some(PathBuf::from("/kotrc/dotfiles")));
+    assert_eq!(args.mode, Some(kfs::InstallMode::Hardlink));
+    assert!(args.dry_run && !args.force);
+    assert_eq!(args.vars["email"], "user@example.com");
+    assert_eq!(args.vars["editor"], "vim");
+    let args = get_args(&["--mode", "symlink"], Some("/env"));
+    assert_eq!(args.install, Some(PathBuf::from("/env")));
+    assert_eq!(args.mode, Some(kfs::InstallMode::Symlink));
+    let args = get_args(&["--install", "/cli"], Some("/env"));
+    assert_eq!(args.install, Some(PathBuf::from("/cli")));
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn dotfiles_are_named() {
+    let dir = kfs::get_test_dir("kcli-names");
+    kfs::create_dir_all(&dir.join("dot/.vim")).unwrap();
+    let get_name = |dotfiles: &Path| {
+      return Cli::from_iter(["kot", dotfiles.to_str().unwrap()]).get_name();
+    };
+    assert_eq!(get_name(&dir.join("dot")).unwrap(), "dot");
+    assert_eq!(get_name(&dir.join("dot/.")).unwrap(), "dot");
+    assert_eq!(get_name(&dir.join("dot/.vim/..")).unwrap(), "dot");
+    assert!(get_name(Path::new("/")).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,151 @@
+/*##############################################################################
+## Author: Shaun Reed                                                         ##
+## Legal: All Content (c) 2021 Shaun Reed, all rights reserved                ##
+## About: Configuration files providing default arguments for kot             ##
+##                                                                            ##
+## Contact: shaunrd0@gmail.com  | URL: www.shaunreed.com | GitHub: shaunrd0   ##
+##############################################################################*/
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use serde::Deserialize;
+
+use super::kfs;
+
+// =============================================================================
+// STRUCTS
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+/// Name of the configuration file kot reads from the root of the dotfiles
+pub const KOTRC: &str = ".kotrc";
+
+/// Default values for CLI arguments, read from a TOML configuration file
+/// + Keys match the long name of each CLI argument, such as backup-dir
+/// + Any argument that is not set is left for the CLI or another configuration file to provide
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+  pub dotfiles: Option<PathBuf>,
+  pub install: Option<PathBuf>,
+  pub backup_dir: Option<PathBuf>,
+  pub clone_dir: Option<PathBuf>,
+  pub force: Option<bool>,
+  pub dry_run: Option<bool>,
+  pub mode: Option<kfs::InstallMode>,
+  pub file_mode: HashMap<PathBuf, kfs::InstallMode>,
+  pub include_repo_files: Option<bool>,
+  pub relative: Option<bool>,
+  pub dot_prefix: Option<bool>,
+  pub no_hooks: Option<bool>,
+  pub package: Vec<String>,
+  pub profile: Vec<String>,
+  pub vars: HashMap<String, String>,
+}
+
+// =============================================================================
+// IMPLEMENTATION
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+/// Constructs a new PathBuf pointing to the user configuration file for kot
+/// + $XDG_CONFIG_HOME/kot/config.toml, where XDG_CONFIG_HOME defaults to $HOME/.config
+pub fn get_user_config_path() -> super::Result<PathBuf> {
+  let mut config_path = match std::env::var_os("XDG_CONFIG_HOME") {
+    Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+    _ => kfs::get_home_dir()?.join(".config"),
+  };
+  config_path.push("kot/config.toml");
+  return Ok(config_path);
+}
+
+impl Config {
+  /// Reads a configuration file
+  /// + If the file does not exist, an empty configuration is returned
+  pub fn load(path: &PathBuf) -> super::Result<Config> {
+    if !path.is_file() {
+      return Ok(Config::default());
+    }
+    let contents = std::fs::read_to_string(path)?;
+    return match toml::from_str(&contents) {
+      Ok(config) => Ok(config),
+      Err(e) => Err(super::kerror::Error::new(
+        super::kerror::ErrorKind::ConfigError(e.to_string()),
+        format!("Unable to parse configuration file: {:?}", path)
+      )),
+    };
+  }
+
+  /// Combines two configurations, preferring values set within self
+  pub fn merge(self, lower: Config) -> Config {
+    let mut file_mode = lower.file_mode;
+    file_mode.extend(self.file_mode);
+    let mut vars = lower.vars;
+    vars.extend(self.vars);
+    return Config {
+      dotfiles: self.dotfiles.or(lower.dotfiles),
+      install: self.install.or(lower.install),
+      backup_dir: self.backup_dir.or(lower.backup_dir),
+      clone_dir: self.clone_dir.or(lower.clone_dir),
+      force: self.force.or(lower.force),
+      dry_run: self.dry_run.or(lower.dry_run),
+      mode: self.mode.or(lower.mode),
+      file_mode,
+      include_repo_files: self.include_repo_files.or(lower.include_repo_files),
+      relative: self.relative.or(lower.relative),
+      dot_prefix: self.dot_prefix.or(lower.dot_prefix),
+      no_hooks: self.no_hooks.or(lower.no_hooks),
+      package: match self.package.is_empty() {
+        true => lower.package,
+        false => self.package,
+      },
+      profile: match self.profile.is_empty() {
+        true => lower.profile,
+        false => self.profile,
+      },
+      vars,
+    };
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn configs_are_merged() {
+    let dir = kfs::get_test_dir("kconfig");
+    assert!(Config::load(&dir.join("missing.toml")).unwrap().install.is_none());
+    kfs::write(&dir.join("unknown.toml"), "instal = \"/home\"\n");
+    assert!(Config::load(&dir.join("unknown.toml")).is_err());
+
+    kfs::write(&dir.join("upper.toml"), r#"
+      install = "/upper"
+      package = ["vim"]
+      file-mode = { ".ssh/config" = "copy" }
+      [vars]
+      email = "upper@example.com"
+    "#);
+    kfs::write(&dir.join("lower.toml"), r#"
+      install = "/lower"
+      backup-dir = "/lower/backups"
+      package = ["bash", "git"]
+      file-mode = { ".ssh/config" = "hardlink", ".gitconfig" = "copy" }
+      [vars]
+      email = "lower@example.com"
+      editor = "vim"
+    "#);
+    let upper = Config::load(&dir.join("upper.toml")).unwrap();
+    let config = upper.merge(Config::load(&dir.join("lower.toml")).unwrap());
+    assert_eq!(config.install, Some(PathBuf::from("/upper")));
+    assert_eq!(config.backup_dir, Some(PathBuf::from("/lower/backups")));
+    assert_eq!(config.package, ["vim"]);
+    assert_eq!(config.file_mode[&PathBuf::from(".ssh/config")], kfs::InstallMode::Copy);
+    assert_eq!(config.file_mode[&PathBuf::from(".gitconfig")], kfs::InstallMode::Copy);
+    assert_eq!(config.vars["email"], "upper@example.com");
+    assert_eq!(config.vars["editor"], "vim");
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -17,6 +17,8 @@ pub enum ErrorKind {
   IOError(String),
   FileError(String),
   DirError(String),
+  SecretError(String),
+  HookError(String),
   Other(String),
 }
 
@@ -73,6 +75,55 @@ impl std::convert::From<fs_extra::error::Error> for Error {
   }
 }
 
+// Converting from toml::de::Error to kot::kerror::Error::ConfigError
+impl std::convert::From<toml::de::Error> for Error {
+  fn from(error: toml::de::Error) -> Self {
+    return Error::new(ErrorKind::ConfigError(error.to_string()),
+                      "(toml parse error)".to_owned());
+  }
+}
+
+// Converting from toml::ser::Error to kot::kerror::Error::ConfigError
+impl std::convert::From<toml::ser::Error> for Error {
+  fn from(error: toml::ser::Error) -> Self {
+    return Error::new(ErrorKind::ConfigError(error.to_string()),
+                      "(toml serialize error)".to_owned());
+  }
+}
+
+// Converting from ignore::Error to kot::kerror::Error::ConfigError
+impl std::convert::From<ignore::Error> for Error {
+  fn from(error: ignore::Error) -> Self {
+    return Error::new(ErrorKind::ConfigError(error.to_string()),
+                      "(.kotignore error)".to_owned());
+  }
+}
+
+// Converting from age::DecryptError to kot::kerror::Error::SecretError
+impl std::convert::From<age::DecryptError> for Error {
+  fn from(error: age::DecryptError) -> Self {
+    return Error::new(ErrorKind::SecretError(error.to_string()),
+                      "(age decrypt error)".to_owned());
+  }
+}
+
+// Converting from age::EncryptError to kot::kerror::Error::SecretError
+impl std::convert::From<age::EncryptError> for Error {
+  fn from(error: age::EncryptError) -> Self {
+    return Error::new(ErrorKind::SecretError(error.to_string()),
+                      "(age encrypt error)".to_owned());
+  }
+}
+
+// Converting from git2::Error to kot::kerror::Error::GitError
+#[cfg(feature = "git2")]
+impl std::convert::From<git2::Error> for Error {
+  fn from(error: git2::Error) -> Self {
+    return Error::new(ErrorKind::GitError(error.message().to_owned()),
+                      format!("(libgit2 {:?} error: {:?})", error.class(), error.code()));
+  }
+}
+
 // -----------------------------------------------------------------------------
 
 
@@ -10,11 +10,86 @@ pub use std::path::{Path, PathBuf};
 pub use std::collections::HashMap;
 pub use fs_extra::dir;
 
+use std::collections::BTreeMap;
 use std::fs;
+use std::os::unix::fs::MetadataExt;
+use serde::{Deserialize, Serialize};
 use crate::kot::err;
 use crate::kot::kerror::{Error, ErrorKind};
 
 use super::kgit;
+use super::kignore;
+use super::ksecret;
+use super::ktemplate;
+
+// =============================================================================
+// STRUCTS
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+/// Prefix of files within the dotfiles that are installed with a leading dot when --dot-prefix is set
+pub const DOT_PREFIX: &str = "dot-";
+
+/// A directory of configurations that is installed together with the layers before it
+/// + The dotfiles are the first layer, and the overlay for each profile is installed on top of them
+/// + Files within later layers shadow files at the same path within earlier layers,
+///     and directories found within more than one layer are merged
+#[derive(Clone)]
+pub struct Layer {
+  /// Directory containing the configurations within this layer
+  pub dir: PathBuf,
+  /// Patterns from each .kotignore between the root of this layer and dir, excluding dir itself
+  pub ignore: kignore::Ignore,
+}
+
+#[cfg(test)]
+thread_local! {
+  /// Data directory used in place of get_data_dir() by the test running on this thread
+  static TEST_DATA_DIR: std::cell::RefCell<Option<PathBuf>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Methods used to install a configuration at its target path
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InstallMode {
+  /// Symbolic link using the absolute path to the configuration
+  #[default]
+  Symlink,
+  /// Symbolic link using the path to the configuration relative to the link
+  RelativeSymlink,
+  /// Hard link to the configuration; Directories are created and each file within is hard linked
+  Hardlink,
+  /// Recursive copy of the configuration
+  Copy,
+}
+
+impl std::str::FromStr for InstallMode {
+  type Err = String;
+
+  fn from_str(mode: &str) -> Result<Self, Self::Err> {
+    return match mode {
+      "symlink" => Ok(InstallMode::Symlink),
+      "relative-symlink" => Ok(InstallMode::RelativeSymlink),
+      "hardlink" => Ok(InstallMode::Hardlink),
+      "copy" => Ok(InstallMode::Copy),
+      _ => Err(format!("Unknown install mode {:?}; \
+                        Expected symlink, relative-symlink, hardlink, or copy", mode)),
+    };
+  }
+}
+
+impl std::fmt::Display for InstallMode {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    let mode = match self {
+      InstallMode::Symlink => "symlink",
+      InstallMode::RelativeSymlink => "relative-symlink",
+      InstallMode::Hardlink => "hardlink",
+      InstallMode::Copy => "copy",
+    };
+    write!(f, "{}", mode)
+  }
+}
 
 // =============================================================================
 // IMPLEMENTATION
@@ -22,6 +97,15 @@ use super::kgit;
 
 // -----------------------------------------------------------------------------
 
+impl Layer {
+  /// Returns the layer for a path within the directory of this layer
+  /// + Adds each .kotignore between the directory of this layer and path
+  pub fn join(&self, path: &Path) -> super::Result<Layer> {
+    let dir: PathBuf = self.dir.join(path).components().collect();
+    return Ok(Layer { ignore: self.ignore.with_parents(&self.dir, &dir)?, dir });
+  }
+}
+
 pub fn abs(dir: &PathBuf) -> super::Result<PathBuf> {
   return match dir.canonicalize() {
     Ok(result) => Ok(result),
@@ -35,30 +119,370 @@ pub fn abs(dir: &PathBuf) -> super::Result<PathBuf> {
 }
 
 /// Initialize and return a HashMap<config_dir, config_install_location>
+/// + Each layer is installed into install_dir, and files within later layers shadow files within earlier layers
+/// + Directories that already exist within install_dir are descended into, and only their contents are linked
+/// + Directories that do not exist within install_dir are linked as a whole, similar to tree folding in GNU stow
+/// + Files matching ignore, or patterns within .kotignore files in the dotfiles are not included
+/// + If dot_prefix is set, names starting with DOT_PREFIX are installed with a leading dot instead
+/// + Templates and secrets are installed without their suffix, see ktemplate and ksecret
+/// + Each (config, target) within targets is installed at target instead of its own path within install_dir;
+///     Configurations within targets are relative to the directory of each layer
 /// + Later used to check each install location for conflicts before installing
 /// + This function does not create or modify any files or directories
-pub fn get_target_paths(install_dir: &PathBuf, dotfiles: &PathBuf)
+pub fn get_target_paths(install_dir: &Path, layers: &[Layer], dot_prefix: bool,
+                        targets: &Vec<(PathBuf, PathBuf)>)
                         -> super::Result<HashMap<PathBuf, PathBuf>> {
   let mut config_map = HashMap::new();
+  // Packages that only list targets do not need a directory
+  let dirs: Vec<_> = layers.iter().filter(|layer| layer.dir.is_dir()).cloned().collect();
+  plan_target_paths(install_dir, &dirs, Path::new(""), dot_prefix, targets, &mut config_map)?;
+
+  // Configurations with a target are planned using the .kotignore files between them and each layer
+  for (source, target_path) in targets.iter() {
+    let found = layers.iter()
+        .filter(|layer| layer.dir.join(source).symlink_metadata().is_ok())
+        .map(|layer| layer.join(source))
+        .collect::<super::Result<Vec<_>>>()?;
+    // The configuration is installed from the last layer that contains it
+    let config_path = match found.last() {
+      Some(layer) => layer.dir.to_owned(),
+      None => err!(
+        ErrorKind::ConfigError(format!("No configuration {:?} within {:?}", source, layers[0].dir)),
+        "Check the targets listed within kot.toml".to_owned()
+      ),
+    };
+    let merged = merge_layers(found);
+    if is_unfolded(target_path, &merged, source, dot_prefix, targets)? {
+      plan_target_paths(target_path, &merged, source, dot_prefix, targets, &mut config_map)?;
+    }
+    else {
+      config_map.insert(config_path, target_path.to_owned());
+    }
+  }
+
+  // Each target can only be installed from a single configuration
+  let mut installed: Vec<_> = config_map.values().collect();
+  installed.sort();
+  if let Some(pair) = installed.windows(2).find(|pair| pair[0] == pair[1]) {
+    err!(
+      ErrorKind::ConfigError(format!("Multiple configurations are installed at {:?}", pair[0])),
+      "Check the targets listed within the dotfiles manifest".to_owned()
+    );
+  }
+  return Ok(config_map);
+}
+
+/// Inserts the target install path of each config within the directories of each layer into config_map
+/// + Each layer is a directory at the same relative path within a different layer, see get_target_paths()
+/// + Configurations within later layers shadow configurations installed at the same name in earlier layers
+/// + Recurses into directories that must be unfolded, see is_unfolded()
+/// + Configurations within targets are skipped, along with directories containing them
+fn plan_target_paths(install_dir: &Path, layers: &[Layer], relative: &Path,
+                     dot_prefix: bool, targets: &Vec<(PathBuf, PathBuf)>,
+                     config_map: &mut HashMap<PathBuf, PathBuf>) -> super::Result<()> {
+  // Each file or directory within the layers by the name it is installed as, in the order of the layers
+  let mut entries: BTreeMap<std::ffi::OsString, Vec<(PathBuf, usize)>> = BTreeMap::new();
+  let mut ignores = vec![];
+  for (index, layer) in layers.iter().enumerate() {
+    let ignore = layer.ignore.with_dir(&layer.dir)?;
+    for config_entry in fs::read_dir(&layer.dir)? {
+      let entry = config_entry?;
+      let config_path = entry.path();
+      if ignore.is_ignored(&config_path, config_path.is_dir())
+          || targets.iter().any(|(source, _)| source == &relative.join(entry.file_name())) {
+        continue;
+      }
+      let mut target_name = get_target_name(&entry.file_name(), dot_prefix);
+      if ktemplate::is_template(&config_path) {
+        target_name = ktemplate::get_target_name(&target_name);
+      }
+      else if ksecret::is_secret(&config_path) {
+        target_name = ksecret::get_target_name(&target_name);
+      }
+      if target_name != entry.file_name() && layer.dir.join(&target_name).symlink_metadata().is_ok() {
+        err!(
+          ErrorKind::ConfigError(format!("{:?} and {:?} are both installed as {:?}",
+                                         config_path, layer.dir.join(&target_name), target_name)),
+          "Remove or rename one of the configurations within the dotfiles".to_owned()
+        );
+      }
+      entries.entry(target_name).or_insert(vec![]).push((config_path, index));
+    }
+    ignores.push(ignore);
+  }
 
   // Local variable for the installation directory as an absolute path
   let mut config_target = install_dir.to_owned();
-  // For each file or directory within the dotfiles we're installing
-  for config_entry in fs::read_dir(&dotfiles)? {
-    let entry = config_entry?;
+  for (target_name, found) in entries {
     // Create full path to target config file (or directory) by push onto install path
-    config_target.push(entry.file_name());
+    config_target.push(target_name);
+    let config_path = found.last().unwrap().0.to_owned();
+    let source = relative.join(config_path.file_name().unwrap());
+    let merged = merge_layers(found.into_iter()
+        .map(|(dir, index)| Layer { dir, ignore: ignores[index].to_owned() })
+        .collect());
 
-    // If the entry doesn't already exist, insert it into the config_map
-    // + Key is full path to source config from dotfiles repo we're installing
-    // + Value is desired full path to config at final install location
-    config_map.entry(entry.path().to_owned())
-        .or_insert(config_target.to_owned());
+    if is_unfolded(&config_target, &merged, &source, dot_prefix, targets)? {
+      plan_target_paths(&config_target, &merged, &source, dot_prefix, targets, config_map)?;
+    }
+    else {
+      // If the entry doesn't already exist, insert it into the config_map
+      // + Key is full path to source config from dotfiles repo we're installing
+      // + Value is desired full path to config at final install location
+      config_map.entry(config_path)
+          .or_insert(config_target.to_owned());
+    }
 
     // Reset config_target to be equal to requested install_dir
     config_target.pop();
   }
-  return Ok(config_map);
+  return Ok(());
+}
+
+/// Returns the directories of a configuration found within each layer that are installed together
+/// + A file within a later layer shadows everything beneath it, so only the directories above it are merged
+/// + Returns an empty list if the configuration within the last layer is not a directory
+fn merge_layers(found: Vec<Layer>) -> Vec<Layer> {
+  let mut merged: Vec<_> = found.into_iter().rev()
+      .take_while(|layer| layer.dir.is_dir())
+      .collect();
+  merged.reverse();
+  return merged;
+}
+
+/// Checks if a directory merged from layers must be unfolded when installed at target
+/// + If the directory already exists at target, only the contents are linked, so other files within it are not conflicts
+/// + Directories we linked during a previous installation are left folded, along with directories within them
+/// + Directories that do not exist at target are unfolded if they contain ignored files, since they can not be
+///     linked as a whole, or if they contain prefixed names, templates, or secrets, since they must be renamed
+/// + Directories containing configurations within targets, or found within more than one layer are also unfolded
+fn is_unfolded(target: &Path, layers: &[Layer], source: &Path, dot_prefix: bool,
+               targets: &[(PathBuf, PathBuf)]) -> super::Result<bool> {
+  let config_path = match layers.last() {
+    Some(layer) => &layer.dir,
+    None => return Ok(false),
+  };
+  if target.is_dir() {
+    // Targets within a directory we linked previously resolve to the configuration itself
+    let resolved = target.canonicalize().ok() == config_path.canonicalize().ok();
+    return Ok(!is_link_to(target, config_path) && !resolved);
+  }
+  if target.symlink_metadata().is_ok() {
+    return Ok(false);
+  }
+  if layers.len() > 1 || targets.iter().any(|(config, _)| config != source && config.starts_with(source)) {
+    return Ok(true);
+  }
+  for layer in layers.iter() {
+    if layer.ignore.has_ignored(&layer.dir)? || has_renamed(&layer.dir, dot_prefix)? {
+      return Ok(true);
+    }
+  }
+  return Ok(false);
+}
+
+/// Returns the name a file or directory within the dotfiles is installed as
+/// + If dot_prefix is set, a leading DOT_PREFIX is replaced by a dot, such as dot-bashrc to .bashrc
+pub fn get_target_name(name: &std::ffi::OsStr, dot_prefix: bool) -> std::ffi::OsString {
+  return match name.to_str() {
+    Some(name) if dot_prefix && name.len() > DOT_PREFIX.len() && name.starts_with(DOT_PREFIX) => {
+      std::ffi::OsString::from(".".to_owned() + &name[DOT_PREFIX.len()..])
+    },
+    _ => name.to_owned(),
+  };
+}
+
+/// Returns the name a file or directory installed as name is stored as within the dotfiles
+/// + If dot_prefix is set, a leading dot is replaced by DOT_PREFIX, such as .bashrc to dot-bashrc
+pub fn get_source_name(name: &std::ffi::OsStr, dot_prefix: bool) -> std::ffi::OsString {
+  return match name.to_str() {
+    Some(name) if dot_prefix && name.len() > 1 && name.starts_with('.') => {
+      std::ffi::OsString::from(DOT_PREFIX.to_owned() + &name[1..])
+    },
+    _ => name.to_owned(),
+  };
+}
+
+/// Checks if any file or directory within dir is installed using a different name
+/// + Templates and secrets are installed without their suffix, and names starting with DOT_PREFIX are renamed if dot_prefix is set
+fn has_renamed(dir: &PathBuf, dot_prefix: bool) -> super::Result<bool> {
+  for entry in fs::read_dir(dir)? {
+    let path = entry?.path();
+    if get_target_name(path.file_name().unwrap(), dot_prefix) != path.file_name().unwrap()
+        || ktemplate::is_template(&path) || ksecret::is_secret(&path)
+        || (path.is_dir() && has_renamed(&path, dot_prefix)?) {
+      return Ok(true);
+    }
+  }
+  return Ok(false);
+}
+
+/// Returns the path a configuration within install_dir is backed up to within backup_dir
+/// + Backups mirror the layout of the configurations within install_dir
+pub fn get_backup_path(backup_dir: &Path, install_dir: &Path, target: &Path) -> PathBuf {
+  let mut backup_path = backup_dir.to_owned();
+  match target.strip_prefix(install_dir) {
+    Ok(relative) => backup_path.push(relative),
+    // Targets outside of install_dir mirror their full path within backup_dir
+    Err(_) => backup_path.push(target.strip_prefix("/").unwrap_or(target)),
+  }
+  return backup_path;
+}
+
+/// Checks if target is a symbolic link that points to config
+/// + Relative links are resolved from the directory containing target
+pub fn is_link_to(target: &Path, config: &Path) -> bool {
+  let link = match fs::read_link(target) {
+    Ok(link) => link,
+    Err(_) => return false,
+  };
+  let mut resolved = target.parent().unwrap().to_path_buf();
+  resolved.push(link);
+  return resolved == config
+      || resolved.canonicalize().is_ok_and(|path| path == config);
+}
+
+/// Returns the symbolic link mode matching the form of the link at target
+/// + Returns None if target is not a symbolic link
+pub fn get_link_mode(target: &PathBuf) -> Option<InstallMode> {
+  return match fs::read_link(target) {
+    Ok(link) if link.is_absolute() => Some(InstallMode::Symlink),
+    Ok(_) => Some(InstallMode::RelativeSymlink),
+    Err(_) => None,
+  };
+}
+
+/// Returns the path to target relative to the directory from
+/// + Both paths must be absolute
+pub fn relative_path(from: &Path, target: &Path) -> PathBuf {
+  let from: Vec<_> = from.components().collect();
+  let target: Vec<_> = target.components().collect();
+  let common = from.iter().zip(target.iter())
+      .take_while(|(a, b)| a == b)
+      .count();
+
+  let mut relative = PathBuf::new();
+  for _ in common..from.len() {
+    relative.push("..");
+  }
+  for component in &target[common..] {
+    relative.push(component);
+  }
+  return relative;
+}
+
+/// Installs config at target using the given mode
+/// + The parent directory of target must exist, and nothing may exist at target
+pub fn install_config(config: &PathBuf, target: &PathBuf, mode: InstallMode)
+                      -> super::Result<()> {
+  match mode {
+    InstallMode::Symlink => std::os::unix::fs::symlink(config, target)?,
+    InstallMode::RelativeSymlink => {
+      let link = relative_path(target.parent().unwrap(), config);
+      std::os::unix::fs::symlink(link, target)?
+    },
+    InstallMode::Hardlink => copy_tree(config, target, true)?,
+    InstallMode::Copy => copy_tree(config, target, false)?,
+  }
+  return Ok(());
+}
+
+/// Checks if the file or directory at target was installed from config using the given mode
+/// + Copies and hard links that were modified after installation are not considered installed
+/// + Absolute and relative symbolic links to config are both considered installed by either symlink mode
+pub fn is_installed(config: &PathBuf, target: &PathBuf, mode: InstallMode) -> bool {
+  return match mode {
+    InstallMode::Symlink | InstallMode::RelativeSymlink => is_link_to(target, config),
+    InstallMode::Hardlink => is_same_tree(config, target, true),
+    InstallMode::Copy => is_same_tree(config, target, false),
+  };
+}
+
+/// Removes each directory that is empty, starting with the innermost directory
+/// + Used to clean up directories created while installing configurations
+pub fn remove_empty_dirs(dirs: &Vec<PathBuf>) -> super::Result<()> {
+  let mut dirs = dirs.to_owned();
+  dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+  for dir in dirs.iter() {
+    if dir.is_dir() && dir_entries(dir)? == 0 {
+      fs::remove_dir(dir)?;
+    }
+  }
+  return Ok(());
+}
+
+/// Removes a configuration installed at target, without following links
+pub fn remove_config(target: &PathBuf) -> super::Result<()> {
+  let meta = target.symlink_metadata()?;
+  match meta.is_dir() {
+    true => fs::remove_dir_all(target)?,
+    false => fs::remove_file(target)?,
+  }
+  return Ok(());
+}
+
+/// Recursively copies or hard links config to target
+/// + Directories are always created, since they can not be hard linked
+fn copy_tree(config: &PathBuf, target: &PathBuf, hardlink: bool) -> std::io::Result<()> {
+  if config.is_dir() {
+    fs::create_dir(target)?;
+    for entry in fs::read_dir(config)? {
+      let entry = entry?;
+      copy_tree(&entry.path(), &target.join(entry.file_name()), hardlink)?;
+    }
+  }
+  else if hardlink {
+    fs::hard_link(config, target)?;
+  }
+  else {
+    fs::copy(config, target)?;
+  }
+  return Ok(());
+}
+
+/// Recursively compares config to target, as they would be after calling copy_tree()
+/// + Hard links are compared by inode, and copies are compared by contents
+fn is_same_tree(config: &PathBuf, target: &PathBuf, hardlink: bool) -> bool {
+  let (config_meta, target_meta) = match (config.metadata(), target.symlink_metadata()) {
+    (Ok(config_meta), Ok(target_meta)) => (config_meta, target_meta),
+    _ => return false,
+  };
+  if target_meta.file_type().is_symlink() || config_meta.is_dir() != target_meta.is_dir() {
+    return false;
+  }
+  if config_meta.is_dir() {
+    let entries = match fs::read_dir(config) {
+      Ok(entries) => entries,
+      Err(_) => return false,
+    };
+    let mut count = 0;
+    for entry in entries.flatten() {
+      count += 1;
+      if !is_same_tree(&entry.path(), &target.join(entry.file_name()), hardlink) {
+        return false;
+      }
+    }
+    // Files added to the directory after installation are modifications
+    return dir_entries(target).is_ok_and(|entries| entries == count);
+  }
+  return match hardlink {
+    true => config_meta.dev() == target_meta.dev() && config_meta.ino() == target_meta.ino(),
+    false => config_meta.len() == target_meta.len()
+        && fs::read(config).ok() == fs::read(target).ok(),
+  };
+}
+
+/// Moves a file or directory to dst, which must not already exist
+/// + Used to move backups and stashed configurations back into place
+pub fn move_path(src: &PathBuf, dst: &PathBuf) -> super::Result<()> {
+  // Renaming is atomic and avoids a copy when src and dst share a filesystem
+  if fs::rename(src, dst).is_ok() {
+    return Ok(());
+  }
+  return match src.is_dir() {
+    true => move_dir(src, dst, None),
+    false => move_file(src, dst),
+  };
 }
 
 /// Moves a single file from one location to another; Can be used to rename files
@@ -122,6 +546,19 @@ pub fn dir_entries(dir: &PathBuf) -> super::Result<usize> {
   return Ok(count);
 }
 
+/// Removes each empty directory containing path, up to but not including root
+/// + Used to clean up the directories left behind after moving a backup out of the backup directory
+pub fn remove_empty_parents(path: &Path, root: &Path) -> super::Result<()> {
+  for dir in path.ancestors().skip(1) {
+    let dir = dir.to_path_buf();
+    if dir == root || !dir.starts_with(root) || dir_entries(&dir)? != 0 {
+      break;
+    }
+    fs::remove_dir(&dir)?;
+  }
+  return Ok(());
+}
+
 /// Stash a directory in the temp folder, staging it for deletion
 /// + We stash first instead of delete to allow recovery of these files if we run into an error
 pub fn stash_dir(dir: &PathBuf) -> super::Result<()> {
@@ -143,6 +580,14 @@ pub fn stash_dir(dir: &PathBuf) -> super::Result<()> {
   return Ok(());
 }
 
+/// Returns the temporary file used while writing path, which is renamed to path once it is written
+/// + Used to write rendered templates and decrypted secrets, so an interrupted write never leaves a partial file
+pub fn get_temp_path(path: &Path) -> PathBuf {
+  let mut temp_path = path.to_owned();
+  temp_path.set_file_name(format!(".{}.tmp", path.file_name().unwrap().to_string_lossy()));
+  return temp_path;
+}
+
 /// Gets the root temp directory used by kot to store expired files as an owned PathBuf
 pub fn get_temp_dir() -> PathBuf {
   // Get temp directory from current user environment
@@ -151,17 +596,105 @@ pub fn get_temp_dir() -> PathBuf {
   return temp;
 }
 
+/// Returns the home directory of the user running kot
+/// + Read from $HOME each time kot is run, so the binary can be used by any user
+pub fn get_home_dir() -> super::Result<PathBuf> {
+  return match std::env::var_os("HOME") {
+    Some(home) if !home.is_empty() => Ok(PathBuf::from(home)),
+    _ => err!(
+      ErrorKind::ConfigError("HOME is not set".to_owned()),
+      "Set HOME, or provide the install directory with --install".to_owned()
+    ),
+  };
+}
+
 /// Constructs a new PathBuf pointing to the default data directory used by kot
-pub fn get_data_dir() -> PathBuf {
-  let mut data_dir = std::path::Path::new(env!("HOME")).to_path_buf();
+pub fn get_data_dir() -> super::Result<PathBuf> {
+  // Tests use a data directory within their test directory, see get_test_dir()
+  #[cfg(test)]
+  if let Some(data_dir) = TEST_DATA_DIR.with(|data_dir| data_dir.borrow().to_owned()) {
+    return Ok(data_dir);
+  }
+  let mut data_dir = get_home_dir()?;
   data_dir.push(".local/share/kot/");
-  return data_dir;
+  return Ok(data_dir);
+}
+
+/// Constructs a new PathBuf pointing to the default backup directory used by kot
+/// + Each backup is stored in a subdirectory named <DOTFILES_NAME>:<DATE>
+pub fn get_backup_root() -> super::Result<PathBuf> {
+  let mut backup_root = get_data_dir()?;
+  backup_root.push("backups/");
+  return Ok(backup_root);
 }
 
 /// Constructs a new PathBuf pointing to the default clone directory used by kot
-pub fn get_repo_path(repo_url: &str) -> PathBuf {
-  let mut repo_path = get_data_dir();
+pub fn get_repo_path(repo_url: &str) -> super::Result<PathBuf> {
+  let mut repo_path = get_data_dir()?;
   // Store the new dotfiles repo in a subdirectory using it's name
-  repo_path.push("dotfiles/".to_owned() + &kgit::repo_name(repo_url) + "/");
-  return repo_path;
+  repo_path.push("dotfiles/".to_owned() + &kgit::repo_name(repo_url)? + "/");
+  return Ok(repo_path);
+}
+
+/// Returns the hostname of this system; Used as the profile when no profiles are provided
+/// + Returns None if the hostname can not be read
+pub fn get_hostname() -> Option<String> {
+  return fs::read_to_string("/proc/sys/kernel/hostname").ok()
+      .or(std::env::var("HOSTNAME").ok())
+      .map(|name| name.trim().to_owned())
+      .filter(|name| !name.is_empty());
+}
+
+/// Creates an empty directory for a test within the system temporary directory
+/// + Anything left within it by a previous run of the same test is removed
+/// + The kot data directory is moved into it for the rest of the test, so the manifest, journal, and backups
+///     of the user running the tests are never read or modified
+#[cfg(test)]
+pub fn get_test_dir(name: &str) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("kot-test-{}-{}", std::process::id(), name));
+  if dir.symlink_metadata().is_ok() {
+    fs::remove_dir_all(&dir).unwrap();
+  }
+  fs::create_dir_all(&dir).unwrap();
+  let dir = dir.canonicalize().unwrap();
+  TEST_DATA_DIR.with(|data_dir| *data_dir.borrow_mut() = Some(dir.join("data")));
+  return dir;
+}
+
+/// Writes a file within a test directory, creating the directories containing it
+#[cfg(test)]
+pub fn write(path: &Path, contents: &str) {
+  fs::create_dir_all(path.parent().unwrap()).unwrap();
+  fs::write(path, contents).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn relative_links() {
+    let dir = get_test_dir("kfs-relative");
+    let (dot, home) = (dir.join("dot"), dir.join("home"));
+    write(&dot.join(".config/nvim/init.vim"), "init");
+    fs::create_dir_all(home.join(".config")).unwrap();
+    assert_eq!(relative_path(&home.join(".config"), &dot.join(".config/nvim")),
+               PathBuf::from("../../dot/.config/nvim"));
+
+    let (config, target) = (dot.join(".config/nvim"), home.join(".config/nvim"));
+    install_config(&config, &target, InstallMode::RelativeSymlink).unwrap();
+    assert_eq!(fs::read_link(&target).unwrap(), PathBuf::from("../../dot/.config/nvim"));
+    assert_eq!(get_link_mode(&target), Some(InstallMode::RelativeSymlink));
+    // Links using either form are installed by both symlink modes
+    assert!(is_installed(&config, &target, InstallMode::RelativeSymlink));
+    assert!(is_installed(&config, &target, InstallMode::Symlink));
+    assert!(!is_link_to(&target, &dot.join(".config")));
+
+    remove_config(&target).unwrap();
+    install_config(&config, &target, InstallMode::Symlink).unwrap();
+    assert_eq!(get_link_mode(&target), Some(InstallMode::Symlink));
+    assert!(is_installed(&config, &target, InstallMode::RelativeSymlink));
+    assert_eq!(get_link_mode(&home.join(".config")), None);
+    fs::remove_dir_all(&dir).unwrap();
+  }
 }
@@ -7,41 +7,707 @@
 ##############################################################################*/
 
 use std::os::linux::raw::stat;
-use std::path::{PathBuf};
-use std::process::{Command};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use regex::Regex;
 use crate::kot::err;
 use super::kerror::{Error, ErrorKind};
 
 use super::kfs;
 
 
+// =============================================================================
+// STRUCTS
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+/// Shorthands for repositories hosted on common forges, and the URL each expands to
+/// + gh:shaunrd0/dot is cloned from https://github.com/shaunrd0/dot
+pub const FORGES: [(&str, &str); 2] = [("gh:", "https://github.com/"), ("gl:", "https://gitlab.com/")];
+
+/// Environment variable that selects the backend used for git operations, see get_backend()
+/// + Set to cli to use the git CLI even when kot is built with the git2 feature
+#[cfg(feature = "git2")]
+pub const BACKEND_ENV: &str = "KOT_GIT_BACKEND";
+
+/// Progress of the objects received from a remote while cloning or fetching a repository
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+  /// Number of objects received so far
+  pub received: usize,
+  /// Number of objects the remote is sending
+  pub total: usize,
+}
+
+/// Operations kot performs on the repositories it clones
+/// + Paths are to the root of the working tree of a repository
+/// + Backends report the objects received from remotes through progress, if they are able to
+pub trait GitBackend {
+  /// Clones a repository into clone_dir, checking out branch if it is provided instead of the default branch
+  /// + branch can also be a tag, which is checked out as a detached HEAD
+  fn clone(&self, repo_url: &str, clone_dir: &Path, branch: Option<&String>,
+           progress: &mut dyn FnMut(&Progress)) -> super::Result<()>;
+  /// Fetches the remotes of a repository, without updating its working tree
+  fn fetch(&self, repo_dir: &Path, progress: &mut dyn FnMut(&Progress)) -> super::Result<()>;
+  /// Fast-forwards the branch checked out within a repository to its upstream
+  fn fast_forward(&self, repo_dir: &Path) -> super::Result<()>;
+  /// Checks out a commit as a detached HEAD
+  fn checkout(&self, repo_dir: &Path, commit: &str) -> super::Result<()>;
+  /// Initializes and updates each submodule to the commit recorded within the repository, recursively
+  fn update_submodules(&self, repo_dir: &Path) -> super::Result<()>;
+  /// Checks if a repository contains changes that are not committed, including untracked files
+  fn is_modified(&self, repo_dir: &Path) -> super::Result<bool>;
+  /// Checks if HEAD points to a commit instead of a branch
+  fn is_detached(&self, repo_dir: &Path) -> super::Result<bool>;
+  /// Resolves a commit, branch, or tag to the hash of a commit, or None if the repository does not contain it
+  fn resolve_rev(&self, repo_dir: &Path, rev: &str) -> super::Result<Option<String>>;
+  /// Stages paths within the repository containing repo_dir, and commits everything staged
+  /// + Paths are absolute, and directories are staged recursively
+  fn commit(&self, repo_dir: &Path, paths: &[PathBuf], message: &str) -> super::Result<()>;
+}
+
+/// Performs git operations by running the git CLI
+/// + Used when kot is built without the git2 feature, or when BACKEND_ENV is set to cli
+pub struct CliBackend;
+
+/// Performs git operations in-process using libgit2
+/// + Credentials are read from the ssh agent for ssh remotes, and from git credential helpers for https remotes
+#[cfg(feature = "git2")]
+pub struct Libgit2Backend;
+
 // =============================================================================
 // IMPLEMENTATION
 // =============================================================================
 
 // -----------------------------------------------------------------------------
 
+/// Returns the backend used for git operations
+/// + libgit2 is used if kot is built with the git2 feature, unless BACKEND_ENV is set to cli
+pub fn get_backend() -> Box<dyn GitBackend> {
+  #[cfg(feature = "git2")]
+  if std::env::var(BACKEND_ENV).map_or(true, |backend| backend != "cli") {
+    return Box::new(Libgit2Backend);
+  }
+  return Box::new(CliBackend);
+}
+
 /// Clones a Git repository using https or ssh
 /// + By default, cloned repositories are stored in $HOME/.local/share/kot/dotfiles/
-pub fn clone(repo_url: &str, clone_dir: &PathBuf)
+/// + If branch is provided, that branch or tag is cloned instead of the default branch
+/// + If rev is provided, it is checked out once the repository is cloned
+/// + Submodules are updated recursively to match the commit checked out
+/// + If the clone fails, anything written to clone_dir is removed
+pub fn clone(repo_url: &str, clone_dir: &Path, branch: Option<&String>, rev: Option<&String>)
   -> super::Result<PathBuf> {
-  // Clone the repository, check that status return value is 0
-  let status = Command::new("git")
-      .args(["clone", repo_url, clone_dir.to_str().unwrap(), "--recursive"])
-      .status().unwrap();
+  // Only clean up directories that did not contain anything before cloning
+  let is_empty = clone_dir.read_dir().map_or(true, |mut entries| entries.next().is_none());
+  let backend = get_backend();
+  println!("Cloning {:?} into {:?}", repo_url, clone_dir);
+  let result = with_progress(|progress| backend.clone(repo_url, clone_dir, branch, progress))
+      .and_then(|_| match rev {
+        Some(rev) => backend.checkout(clone_dir, &resolve_rev(backend.as_ref(), clone_dir, rev)?),
+        None => Ok(()),
+      })
+      .and_then(|_| backend.update_submodules(clone_dir));
+  if let Err(e) = result {
+    if is_empty && clone_dir.exists() {
+      std::fs::remove_dir_all(clone_dir)?;
+    }
+    return Err(e);
+  }
+  return Ok(clone_dir.to_owned());
+}
+
+/// Fetches and fast-forwards the branch checked out within a cloned repository, and updates its submodules
+/// + Returns an error if HEAD is detached, or the branch has diverged from its upstream
+pub fn update(repo_dir: &Path) -> super::Result<()> {
+  let backend = get_backend();
+  if backend.is_detached(repo_dir)? {
+    err!(
+      ErrorKind::GitError(format!("No branch is checked out within {:?}", repo_dir)),
+      "Repositories checked out at a revision with --rev can not be updated".to_owned()
+    );
+  }
+  with_progress(|progress| backend.fetch(repo_dir, progress))?;
+  backend.fast_forward(repo_dir)?;
+  backend.update_submodules(repo_dir)?;
+  return Ok(());
+}
+
+/// Checks if a repository contains changes that are not committed, including untracked files
+pub fn is_modified(repo_dir: &Path) -> super::Result<bool> {
+  return get_backend().is_modified(repo_dir);
+}
+
+/// Commits paths within the repository containing repo_dir, and returns the hash of the new commit
+pub fn commit(repo_dir: &Path, paths: &[PathBuf], message: &str) -> super::Result<String> {
+  let backend = get_backend();
+  backend.commit(repo_dir, paths, message)?;
+  return resolve_rev(backend.as_ref(), repo_dir, "HEAD");
+}
+
+/// Returns the hash of the commit checked out within a repository
+pub fn get_commit(repo_dir: &Path) -> super::Result<String> {
+  return resolve_rev(get_backend().as_ref(), repo_dir, "HEAD");
+}
+
+/// Resolves a commit, branch, or tag within a repository to the hash of a commit
+/// + Branches that only exist on the origin remote are also resolved, since clones only create the default branch
+fn resolve_rev(backend: &dyn GitBackend, repo_dir: &Path, rev: &str) -> super::Result<String> {
+  for candidate in [rev.to_owned(), format!("origin/{}", rev)] {
+    if let Some(commit) = backend.resolve_rev(repo_dir, &candidate)? {
+      return Ok(commit);
+    }
+  }
+  err!(
+    ErrorKind::GitError(format!("Unknown revision {:?}", rev)),
+    format!("No commit, branch, or tag {:?} within {:?}", rev, repo_dir)
+  );
+}
+
+/// Runs a git operation, printing the progress it reports on a single line
+fn with_progress<F>(operation: F) -> super::Result<()>
+  where F: FnOnce(&mut dyn FnMut(&Progress)) -> super::Result<()> {
+  let mut printed = false;
+  let mut received = None;
+  let result = operation(&mut |progress| {
+    // Progress is also reported while received objects are indexed, which does not need to be printed
+    if progress.total > 0 && received != Some(progress.received) {
+      received = Some(progress.received);
+      print!("\r  Receiving objects: {:>3}% ({}/{})",
+             progress.received * 100 / progress.total, progress.received, progress.total);
+      std::io::stdout().flush().ok();
+      printed = true;
+    }
+  });
+  if printed {
+    println!();
+  }
+  return result;
+}
 
-  return match status.code() {
-    Some(0) => Ok(clone_dir.to_owned()),
-    _ => {
-      return
-          err!(ErrorKind::GitError(status.to_string()),
-                   format!("Unable to clone repository"));
+impl CliBackend {
+  /// Runs git within repo_dir, returning its output if it exits successfully
+  /// + If git fails, the error contains each line git wrote to stderr
+  /// + Arguments are passed to git as provided, so paths do not need to be valid UTF-8
+  fn run<S: AsRef<OsStr>>(&self, repo_dir: Option<&Path>, args: &[S], message: String)
+    -> super::Result<String> {
+    let mut command = Command::new("git");
+    if let Some(repo_dir) = repo_dir {
+      command.arg("-C").arg(repo_dir);
+    }
+    let output = command.args(args).stdin(Stdio::null()).output()?;
+    if !output.status.success() {
+      let stderr: Vec<_> = String::from_utf8_lossy(&output.stderr).lines().map(|line| line.trim().to_owned())
+          .filter(|line| !line.is_empty()).collect();
+      err!(
+        ErrorKind::GitError(match stderr.is_empty() {
+          true => output.status.to_string(),
+          false => stderr.join("; "),
+        }),
+        message
+      );
     }
+    return Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned());
   }
 }
 
+impl GitBackend for CliBackend {
+  fn clone(&self, repo_url: &str, clone_dir: &Path, branch: Option<&String>,
+           _progress: &mut dyn FnMut(&Progress)) -> super::Result<()> {
+    let mut args: Vec<&OsStr> = vec!["clone".as_ref(), "--quiet".as_ref(), OsStr::new(repo_url), clone_dir.as_os_str()];
+    if let Some(branch) = branch {
+      args.extend([OsStr::new("--branch"), OsStr::new(branch)]);
+    }
+    self.run(None, &args, "Unable to clone repository".to_owned())?;
+    return Ok(());
+  }
+
+  fn fetch(&self, repo_dir: &Path, _progress: &mut dyn FnMut(&Progress)) -> super::Result<()> {
+    self.run(Some(repo_dir), &["fetch", "--quiet", "--recurse-submodules=no"],
+             "Unable to fetch repository".to_owned())?;
+    return Ok(());
+  }
+
+  fn fast_forward(&self, repo_dir: &Path) -> super::Result<()> {
+    self.run(Some(repo_dir), &["merge", "--quiet", "--ff-only", "@{upstream}"],
+             "Unable to fast-forward repository; The branch may have diverged from its upstream".to_owned())?;
+    return Ok(());
+  }
+
+  fn checkout(&self, repo_dir: &Path, commit: &str) -> super::Result<()> {
+    self.run(Some(repo_dir), &["checkout", "--quiet", "--detach", commit],
+             format!("Unable to check out revision {:?}", commit))?;
+    return Ok(());
+  }
+
+  fn update_submodules(&self, repo_dir: &Path) -> super::Result<()> {
+    self.run(Some(repo_dir), &["submodule", "--quiet", "update", "--init", "--recursive"],
+             "Unable to update submodules".to_owned())?;
+    return Ok(());
+  }
+
+  fn is_modified(&self, repo_dir: &Path) -> super::Result<bool> {
+    let status = self.run(Some(repo_dir), &["status", "--porcelain"],
+                          format!("Unable to check the status of {:?}", repo_dir))?;
+    return Ok(!status.is_empty());
+  }
+
+  fn is_detached(&self, repo_dir: &Path) -> super::Result<bool> {
+    let status = Command::new("git").arg("-C").arg(repo_dir)
+        .args(["symbolic-ref", "--quiet", "HEAD"]).stdout(Stdio::null()).status()?;
+    return Ok(!status.success());
+  }
+
+  fn resolve_rev(&self, repo_dir: &Path, rev: &str) -> super::Result<Option<String>> {
+    let output = Command::new("git").arg("-C").arg(repo_dir)
+        .args(["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", rev)])
+        .output()?;
+    return match output.status.success() {
+      true => Ok(Some(String::from_utf8_lossy(&output.stdout).trim().to_owned())),
+      false => Ok(None),
+    };
+  }
+
+  fn commit(&self, repo_dir: &Path, paths: &[PathBuf], message: &str) -> super::Result<()> {
+    let mut args: Vec<&OsStr> = vec!["add".as_ref(), "--".as_ref()];
+    args.extend(paths.iter().map(|path| path.as_os_str()));
+    self.run(Some(repo_dir), &args, format!("Unable to stage changes within {:?}", repo_dir))?;
+    self.run(Some(repo_dir), &["commit", "--quiet", "-m", message],
+             format!("Unable to commit changes within {:?}", repo_dir))?;
+    return Ok(());
+  }
+}
+
+/// Returns callbacks that report transfer progress and provide credentials to libgit2
+/// + Credentials are only attempted a few times, since libgit2 asks again each time they are rejected
+#[cfg(feature = "git2")]
+fn get_callbacks<'a>(progress: &'a mut dyn FnMut(&Progress)) -> git2::RemoteCallbacks<'a> {
+  let mut callbacks = git2::RemoteCallbacks::new();
+  let mut attempts = 0;
+  callbacks.credentials(move |url, username, allowed| {
+    attempts += 1;
+    if attempts > 3 {
+      return Err(git2::Error::from_str(&format!("Authentication failed for {:?}", url)));
+    }
+    if allowed.contains(git2::CredentialType::SSH_KEY) {
+      return git2::Cred::ssh_key_from_agent(username.unwrap_or("git"));
+    }
+    if allowed.contains(git2::CredentialType::USER_PASS_PLAINTEXT) {
+      return git2::Cred::credential_helper(&git2::Config::open_default()?, url, username);
+    }
+    return git2::Cred::default();
+  });
+  callbacks.transfer_progress(move |stats| {
+    progress(&Progress { received: stats.received_objects(), total: stats.total_objects() });
+    return true;
+  });
+  return callbacks;
+}
+
+#[cfg(feature = "git2")]
+impl Libgit2Backend {
+  /// Initializes and updates each submodule within an open repository, recursively
+  fn update_submodules(&self, repo: &git2::Repository) -> super::Result<()> {
+    // Progress is not reported for submodules, since each reports its own objects
+    let mut progress = |_: &Progress| { };
+    for mut submodule in repo.submodules()? {
+      let mut fetch_options = git2::FetchOptions::new();
+      fetch_options.remote_callbacks(get_callbacks(&mut progress));
+      let mut options = git2::SubmoduleUpdateOptions::new();
+      options.fetch(fetch_options);
+      submodule.update(true, Some(&mut options))?;
+      self.update_submodules(&submodule.open()?)?;
+    }
+    return Ok(());
+  }
+}
+
+#[cfg(feature = "git2")]
+impl GitBackend for Libgit2Backend {
+  fn clone(&self, repo_url: &str, clone_dir: &Path, branch: Option<&String>,
+           progress: &mut dyn FnMut(&Progress)) -> super::Result<()> {
+    let mut fetch_options = git2::FetchOptions::new();
+    fetch_options.remote_callbacks(get_callbacks(progress));
+    let repo = git2::build::RepoBuilder::new().fetch_options(fetch_options).clone(repo_url, clone_dir)?;
+    let branch = match branch {
+      Some(branch) => branch,
+      None => return Ok(()),
+    };
+
+    // libgit2 can only clone branches, so tags are checked out once the default branch is cloned
+    let remote_branch = format!("origin/{}", branch);
+    if let Ok(remote) = repo.find_branch(&remote_branch, git2::BranchType::Remote) {
+      let commit = remote.get().peel_to_commit()?;
+      if repo.find_branch(branch, git2::BranchType::Local).is_err() {
+        repo.branch(branch, &commit, false)?.set_upstream(Some(&remote_branch))?;
+      }
+      repo.checkout_tree(commit.as_object(), Some(git2::build::CheckoutBuilder::new().safe()))?;
+      repo.set_head(&format!("refs/heads/{}", branch))?;
+      return Ok(());
+    }
+    return match repo.revparse_single(&format!("refs/tags/{}", branch)) {
+      Ok(tag) => self.checkout(clone_dir, &tag.peel_to_commit()?.id().to_string()),
+      Err(_) => err!(
+        ErrorKind::GitError(format!("No branch or tag {:?} within {:?}", branch, repo_url)),
+        "Unable to clone repository".to_owned()
+      ),
+    };
+  }
+
+  fn fetch(&self, repo_dir: &Path, progress: &mut dyn FnMut(&Progress)) -> super::Result<()> {
+    let repo = git2::Repository::open(repo_dir)?;
+    let mut fetch_options = git2::FetchOptions::new();
+    fetch_options.remote_callbacks(get_callbacks(progress));
+    repo.find_remote("origin")?.fetch(&[] as &[&str], Some(&mut fetch_options), None)?;
+    return Ok(());
+  }
+
+  fn fast_forward(&self, repo_dir: &Path) -> super::Result<()> {
+    let repo = git2::Repository::open(repo_dir)?;
+    let mut head = repo.head()?;
+    let upstream = git2::Branch::wrap(repo.head()?).upstream()?;
+    let target = repo.reference_to_annotated_commit(upstream.get())?;
+    let (analysis, _) = repo.merge_analysis(&[&target])?;
+    if analysis.is_up_to_date() {
+      return Ok(());
+    }
+    if !analysis.is_fast_forward() {
+      err!(
+        ErrorKind::GitError(format!("Branch {:?} has diverged from its upstream", head.shorthand().unwrap_or("HEAD"))),
+        "Unable to fast-forward repository".to_owned()
+      );
+    }
+    // Safe checkouts fail instead of overwriting local modifications
+    let commit = repo.find_object(target.id(), None)?;
+    repo.checkout_tree(&commit, Some(git2::build::CheckoutBuilder::new().safe()))?;
+    head.set_target(target.id(), "kot: fast-forward")?;
+    return Ok(());
+  }
+
+  fn checkout(&self, repo_dir: &Path, commit: &str) -> super::Result<()> {
+    let repo = git2::Repository::open(repo_dir)?;
+    let oid = git2::Oid::from_str(commit)?;
+    repo.checkout_tree(&repo.find_object(oid, None)?, Some(git2::build::CheckoutBuilder::new().safe()))?;
+    repo.set_head_detached(oid)?;
+    return Ok(());
+  }
+
+  fn update_submodules(&self, repo_dir: &Path) -> super::Result<()> {
+    return Libgit2Backend::update_submodules(self, &git2::Repository::open(repo_dir)?);
+  }
+
+  fn is_modified(&self, repo_dir: &Path) -> super::Result<bool> {
+    let repo = git2::Repository::open(repo_dir)?;
+    let mut options = git2::StatusOptions::new();
+    options.include_untracked(true).include_ignored(false);
+    return Ok(!repo.statuses(Some(&mut options))?.is_empty());
+  }
+
+  fn is_detached(&self, repo_dir: &Path) -> super::Result<bool> {
+    return Ok(git2::Repository::open(repo_dir)?.head_detached()?);
+  }
+
+  fn resolve_rev(&self, repo_dir: &Path, rev: &str) -> super::Result<Option<String>> {
+    let repo = git2::Repository::open(repo_dir)?;
+    return Ok(repo.revparse_single(rev).and_then(|object| object.peel_to_commit()).ok()
+        .map(|commit| commit.id().to_string()));
+  }
+
+  fn commit(&self, repo_dir: &Path, paths: &[PathBuf], message: &str) -> super::Result<()> {
+    let repo = git2::Repository::discover(repo_dir)?;
+    let workdir = match repo.workdir() {
+      Some(workdir) => workdir.canonicalize()?,
+      None => err!(
+        ErrorKind::GitError(format!("Repository {:?} has no working tree", repo.path())),
+        "Unable to commit changes to a bare repository".to_owned()
+      ),
+    };
+    // Index paths are relative to the root of the working tree
+    let pathspecs = paths.iter()
+        .map(|path| match path.strip_prefix(&workdir) {
+          Ok(relative) => Ok(relative.to_owned()),
+          Err(_) => err!(
+            ErrorKind::GitError(format!("{:?} is not within the working tree {:?}", path, workdir)),
+            format!("Unable to commit changes within {:?}", repo_dir)
+          ),
+        })
+        .collect::<super::Result<Vec<_>>>()?;
+    let mut index = repo.index()?;
+    index.add_all(pathspecs.iter(), git2::IndexAddOption::DEFAULT, None)?;
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let signature = repo.signature()?;
+    let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+    let parents: Vec<_> = parent.iter().collect();
+    repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)?;
+    return Ok(());
+  }
+}
+
+/// Returns the URL to clone if dotfiles provided to the CLI are a git repository, or None if they are a local directory
+/// + https, http, ssh, git, and file URLs are used as provided, along with scp-like sources such as git@host:user/repo
+/// + Forge shorthands such as gh:user/repo and gl:user/repo are expanded, see FORGES
+/// + Local bare repositories are cloned like any other repository; Other local directories are installed in place
+/// + Sources that look like a repository but do not name one are an error
+pub fn get_repo_url(dotfiles: &Path) -> super::Result<Option<String>> {
+  let source = match dotfiles.to_str() {
+    Some(source) => source,
+    None => return Ok(None),
+  };
+  let shorthand = Regex::new(r"^[\w.-]+(/[\w.-]+)+$").unwrap();
+  for (prefix, forge) in FORGES.iter() {
+    if let Some(path) = source.strip_prefix(prefix) {
+      if !shorthand.is_match(path) {
+        err!(
+          ErrorKind::ConfigError(format!("Invalid repository shorthand {:?}", source)),
+          format!("Shorthands are written as {}<USER>/<REPO>", prefix)
+        );
+      }
+      return Ok(Some(format!("{}{}", forge, path)));
+    }
+  }
+
+  let url = Regex::new(r"^(https?|ssh|git|file)://[^/]*(/.*)$").unwrap();
+  let scp = Regex::new(r"^[\w.-]+@[\w.-]+:(.+)$").unwrap();
+  let path = match (url.captures(source), scp.captures(source)) {
+    (Some(captures), _) => captures[2].to_owned(),
+    (None, Some(captures)) => captures[1].to_owned(),
+    (None, None) if source.contains("://") => err!(
+      ErrorKind::ConfigError(format!("Unsupported repository URL {:?}", source)),
+      "Repository URLs must use https, http, ssh, git, or file, and include the path to the repository".to_owned()
+    ),
+    (None, None) if is_bare_repo(dotfiles) => return Ok(Some(source.to_owned())),
+    (None, None) => return Ok(None),
+  };
+  // Ensure the URL names a repository, so it can be cloned into a directory named after it
+  if repo_name(&path).is_err() {
+    err!(
+      ErrorKind::ConfigError(format!("No repository within {:?}", source)),
+      "Include the path to the repository, such as https://gitlab.com/shaunrd0/dot.git".to_owned()
+    );
+  }
+  return Ok(Some(source.to_owned()));
+}
+
+/// Checks if a local directory is a bare git repository, which has no working tree to install from
+fn is_bare_repo(path: &Path) -> bool {
+  return path.join("HEAD").is_file() && path.join("objects").is_dir() && path.join("refs").is_dir();
+}
+
 /// Extracts repository name from URL
-pub fn repo_name(repo_url: &str) -> String {
-  return repo_url.rsplit_once('/').unwrap().1
-      .strip_suffix(".git").unwrap().to_owned();
+/// + Trailing slashes and the .git suffix are not part of the name
+pub fn repo_name(repo_url: &str) -> super::Result<String> {
+  let path = repo_url.trim_end_matches('/');
+  let name = path.rsplit(['/', ':']).next().unwrap_or(path);
+  let name = name.strip_suffix(".git").unwrap_or(name);
+  if name.is_empty() || name == "." || name == ".." {
+    err!(
+      ErrorKind::ConfigError(format!("Unable to find the repository name within {:?}", repo_url)),
+      "Provide the URL of a repository, such as https://gitlab.com/shaunrd0/dot.git".to_owned()
+    );
+  }
+  return Ok(name.to_owned());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Runs git within dir, panicking if it fails
+  fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output().unwrap();
+    assert!(output.status.success(), "git {:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    return String::from_utf8_lossy(&output.stdout).trim().to_owned();
+  }
+
+  /// Commits a new file within a repository, returning the hash of the commit
+  fn commit_file(repo_dir: &Path, name: &str) -> String {
+    std::fs::write(repo_dir.join(name), name).unwrap();
+    git(repo_dir, &["add", name]);
+    git(repo_dir, &["-c", "user.name=kot", "-c", "user.email=kot@localhost", "commit", "--quiet", "-m", name]);
+    return git(repo_dir, &["rev-parse", "HEAD"]);
+  }
+
+  /// Clones, updates, and commits to a local bare repository using a backend
+  /// + Commits are pushed to the bare repository from a separate working tree, which the clone then fetches
+  fn check_backend(backend: &dyn GitBackend, name: &str) {
+    let dir = kfs::get_test_dir(name);
+    let work_dir = dir.join("work");
+    let bare_dir = dir.join("dot.git");
+    let clone_dir = dir.join("clone");
+    std::fs::create_dir(&work_dir).unwrap();
+    git(&work_dir, &["init", "--quiet"]);
+    git(&work_dir, &["symbolic-ref", "HEAD", "refs/heads/main"]);
+    let first = commit_file(&work_dir, "first");
+    git(&dir, &["clone", "--quiet", "--bare", "work", "dot.git"]);
+    assert_eq!(get_repo_url(&bare_dir).unwrap(), Some(bare_dir.to_str().unwrap().to_owned()));
+
+    backend.clone(bare_dir.to_str().unwrap(), &clone_dir, None, &mut |_| { }).unwrap();
+    assert!(clone_dir.join("first").is_file());
+    assert_eq!(backend.resolve_rev(&clone_dir, "HEAD").unwrap(), Some(first.to_owned()));
+    assert_eq!(backend.resolve_rev(&clone_dir, "main").unwrap(), Some(first.to_owned()));
+    assert_eq!(backend.resolve_rev(&clone_dir, "unknown").unwrap(), None);
+    assert!(!backend.is_detached(&clone_dir).unwrap());
+    assert!(!backend.is_modified(&clone_dir).unwrap());
+
+    let second = commit_file(&work_dir, "second");
+    git(&work_dir, &["push", "--quiet", bare_dir.to_str().unwrap(), "main"]);
+    backend.fetch(&clone_dir, &mut |_| { }).unwrap();
+    assert!(!clone_dir.join("second").exists());
+    backend.fast_forward(&clone_dir).unwrap();
+    assert!(clone_dir.join("second").is_file());
+    assert_eq!(backend.resolve_rev(&clone_dir, "HEAD").unwrap(), Some(second.to_owned()));
+
+    git(&clone_dir, &["config", "user.name", "kot"]);
+    git(&clone_dir, &["config", "user.email", "kot@localhost"]);
+    std::fs::create_dir(clone_dir.join("vim")).unwrap();
+    std::fs::write(clone_dir.join("vim/.vimrc"), "set number").unwrap();
+    assert!(backend.is_modified(&clone_dir).unwrap());
+    backend.commit(&clone_dir, &[clone_dir.join("vim")], "Adopt vim").unwrap();
+    assert!(!backend.is_modified(&clone_dir).unwrap());
+    let third = backend.resolve_rev(&clone_dir, "HEAD").unwrap().unwrap();
+    assert_ne!(third, second);
+    assert_eq!(git(&clone_dir, &["log", "-1", "--format=%s"]), "Adopt vim");
+    assert_eq!(git(&clone_dir, &["show", "--name-only", "--format=", "HEAD"]), "vim/.vimrc");
+    assert!(backend.commit(&clone_dir, &[dir.join("outside")], "Outside").is_err());
+
+    backend.checkout(&clone_dir, &first).unwrap();
+    assert!(backend.is_detached(&clone_dir).unwrap());
+    assert!(!clone_dir.join("second").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  /// Returns the repository URL found for a source, or the message of the error returned
+  fn get_url(source: &str) -> std::result::Result<Option<String>, String> {
+    return get_repo_url(Path::new(source)).map_err(|e| match e.kind {
+      ErrorKind::ConfigError(message) => message,
+      kind => panic!("Unexpected error {:?} for {:?}", kind, source),
+    });
+  }
+
+  #[test]
+  fn repo_urls() {
+    for source in ["https://gitlab.com/shaunrd0/dot.git", "http://localhost/shaunrd0/dot",
+                   "ssh://git@localhost:2222/shaunrd0/dot.git", "git://localhost/dot", "file:///srv/git/dot.git/",
+                   "git@github.com:shaunrd0/dot.git", "git@localhost:dot"] {
+      assert_eq!(get_url(source), Ok(Some(source.to_owned())));
+    }
+    assert_eq!(get_url("gh:shaunrd0/dot"), Ok(Some("https://github.com/shaunrd0/dot".to_owned())));
+    assert_eq!(get_url("gl:shaunrd0/group/dot.git"), Ok(Some("https://gitlab.com/shaunrd0/group/dot.git".to_owned())));
+    assert_eq!(get_url("dotfiles/dot"), Ok(None));
+    assert_eq!(get_url("/home/shaunrd0/dot"), Ok(None));
+  }
+
+  #[test]
+  fn invalid_repo_urls() {
+    assert_eq!(get_url("gh:dot"), Err("Invalid repository shorthand \"gh:dot\"".to_owned()));
+    assert_eq!(get_url("gl:shaunrd0/"), Err("Invalid repository shorthand \"gl:shaunrd0/\"".to_owned()));
+    assert_eq!(get_url("ftp://localhost/dot"), Err("Unsupported repository URL \"ftp://localhost/dot\"".to_owned()));
+    assert_eq!(get_url("https://localhost"), Err("Unsupported repository URL \"https://localhost\"".to_owned()));
+    for source in ["https://localhost/", "ssh://git@localhost/.git", "git@localhost:/", "file:///srv/.."] {
+      assert_eq!(get_url(source), Err(format!("No repository within {:?}", source)));
+    }
+  }
+
+  #[test]
+  fn repo_names() {
+    assert_eq!(repo_name("https://gitlab.com/shaunrd0/dot.git").unwrap(), "dot");
+    assert_eq!(repo_name("https://gitlab.com/shaunrd0/dot/").unwrap(), "dot");
+    assert_eq!(repo_name("git@github.com:dot.git").unwrap(), "dot");
+    assert_eq!(repo_name("/srv/git/dotfiles.git").unwrap(), "dotfiles");
+    for repo_url in ["/", "https://gitlab.com/shaunrd0/.git", "/srv/git/..", "git@github.com:"] {
+      assert!(repo_name(repo_url).is_err(), "{:?}", repo_url);
+    }
+  }
+
+  /// Clones branches and tags, and resolves revisions within a local bare repository using a backend
+  fn check_revisions(backend: &dyn GitBackend, name: &str) {
+    let dir = kfs::get_test_dir(name);
+    let work_dir = dir.join("work");
+    let bare_dir = dir.join("dot.git");
+    std::fs::create_dir(&work_dir).unwrap();
+    git(&work_dir, &["init", "--quiet"]);
+    git(&work_dir, &["symbolic-ref", "HEAD", "refs/heads/main"]);
+    let first = commit_file(&work_dir, "first");
+    git(&work_dir, &["tag", "v1"]);
+    git(&work_dir, &["checkout", "--quiet", "-b", "dev"]);
+    let dev = commit_file(&work_dir, "dev");
+    git(&work_dir, &["checkout", "--quiet", "main"]);
+    let second = commit_file(&work_dir, "second");
+    git(&dir, &["clone", "--quiet", "--bare", "work", "dot.git"]);
+    let repo_url = bare_dir.to_str().unwrap();
+
+    let clone_dir = dir.join("dev");
+    backend.clone(repo_url, &clone_dir, Some(&"dev".to_owned()), &mut |_| { }).unwrap();
+    assert_eq!(backend.resolve_rev(&clone_dir, "HEAD").unwrap(), Some(dev.to_owned()));
+    assert!(!backend.is_detached(&clone_dir).unwrap());
+
+    // Tags are checked out as a detached HEAD
+    let clone_dir = dir.join("v1");
+    backend.clone(repo_url, &clone_dir, Some(&"v1".to_owned()), &mut |_| { }).unwrap();
+    assert_eq!(backend.resolve_rev(&clone_dir, "HEAD").unwrap(), Some(first.to_owned()));
+    assert!(backend.is_detached(&clone_dir).unwrap());
+    assert!(backend.clone(repo_url, &dir.join("unknown"), Some(&"unknown".to_owned()), &mut |_| { }).is_err());
+
+    // Branches that were not checked out are resolved using the origin remote
+    let clone_dir = dir.join("main");
+    backend.clone(repo_url, &clone_dir, None, &mut |_| { }).unwrap();
+    assert_eq!(resolve_rev(backend, &clone_dir, "HEAD").unwrap(), second);
+    assert_eq!(resolve_rev(backend, &clone_dir, "dev").unwrap(), dev);
+    assert_eq!(resolve_rev(backend, &clone_dir, "v1").unwrap(), first);
+    assert_eq!(resolve_rev(backend, &clone_dir, &first[..10]).unwrap(), first);
+    assert!(matches!(resolve_rev(backend, &clone_dir, "unknown").unwrap_err().kind, ErrorKind::GitError(_)));
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn cloned_revisions() {
+    let dir = kfs::get_test_dir("kgit-revisions");
+    let work_dir = dir.join("work");
+    std::fs::create_dir(&work_dir).unwrap();
+    git(&work_dir, &["init", "--quiet"]);
+    let first = commit_file(&work_dir, "first");
+    commit_file(&work_dir, "second");
+
+    // Repositories checked out at a revision are detached, so they can not be updated
+    let clone_dir = dir.join("clone");
+    let rev = first[..10].to_owned();
+    clone(work_dir.to_str().unwrap(), &clone_dir, None, Some(&rev)).unwrap();
+    assert_eq!(get_commit(&clone_dir).unwrap(), first);
+    assert!(!clone_dir.join("second").exists());
+    assert!(update(&clone_dir).is_err());
+
+    // Clones that fail to check out a revision are removed
+    let clone_dir = dir.join("unknown");
+    assert!(clone(work_dir.to_str().unwrap(), &clone_dir, None, Some(&"unknown".to_owned())).is_err());
+    assert!(!clone_dir.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn cli_revisions() {
+    check_revisions(&CliBackend, "kgit-cli-revisions");
+  }
+
+  #[cfg(feature = "git2")]
+  #[test]
+  fn libgit2_revisions() {
+    check_revisions(&Libgit2Backend, "kgit-libgit2-revisions");
+  }
+
+  #[test]
+  fn cli_backend() {
+    check_backend(&CliBackend, "kgit-cli");
+  }
+
+  #[cfg(feature = "git2")]
+  #[test]
+  fn libgit2_backend() {
+    check_backend(&Libgit2Backend, "kgit-libgit2");
+  }
 }
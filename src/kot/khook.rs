@@ -0,0 +1,155 @@
+/*##############################################################################
+## Author: Shaun Reed                                                         ##
+## Legal: All Content (c) 2021 Shaun Reed, all rights reserved                ##
+## About: Hooks run before and after dotfiles are installed or uninstalled    ##
+##                                                                            ##
+## Contact: shaunrd0@gmail.com  | URL: www.shaunreed.com | GitHub: shaunrd0   ##
+##############################################################################*/
+
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use serde::Deserialize;
+
+use crate::kot::err;
+use crate::kot::kerror::{Error, ErrorKind};
+use super::krepo;
+
+// =============================================================================
+// STRUCTS
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+/// Commands listed within kot.toml that are run before and after a package is installed or uninstalled
+/// + Each command is run using sh from the package directory, or the dotfiles if the package has no directory
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Hooks {
+  /// Run before any configuration is installed; Failure aborts the installation
+  pub pre_install: Option<String>,
+  /// Run after every configuration is installed; Failure rolls back the installation
+  pub post_install: Option<String>,
+  /// Run before any configuration is uninstalled; Failure aborts uninstalling the package
+  pub pre_uninstall: Option<String>,
+  /// Run after every configuration is uninstalled
+  pub post_uninstall: Option<String>,
+}
+
+/// Points during installation and uninstallation where hooks are run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hook {
+  PreInstall,
+  PostInstall,
+  PreUninstall,
+  PostUninstall,
+}
+
+// =============================================================================
+// IMPLEMENTATION
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+impl std::fmt::Display for Hook {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    let hook = match self {
+      Hook::PreInstall => "pre-install",
+      Hook::PostInstall => "post-install",
+      Hook::PreUninstall => "pre-uninstall",
+      Hook::PostUninstall => "post-uninstall",
+    };
+    write!(f, "{}", hook)
+  }
+}
+
+impl Hooks {
+  /// Checks if no hooks are listed
+  pub fn is_empty(&self) -> bool {
+    return self.pre_install.is_none() && self.post_install.is_none()
+        && self.pre_uninstall.is_none() && self.post_uninstall.is_none();
+  }
+
+  /// Returns the command listed for a hook, if any
+  pub fn get(&self, hook: Hook) -> Option<&String> {
+    return match hook {
+      Hook::PreInstall => self.pre_install.as_ref(),
+      Hook::PostInstall => self.post_install.as_ref(),
+      Hook::PreUninstall => self.pre_uninstall.as_ref(),
+      Hook::PostUninstall => self.post_uninstall.as_ref(),
+    };
+  }
+
+  /// Runs the command listed for a hook, printing its output once it exits
+  /// + Hooks can read KOT_DOTFILES, KOT_PACKAGE, KOT_INSTALL_DIR, and KOT_HOOK from the environment
+  /// + Hooks do not read from stdin, so they can not prompt the user
+  /// + A hook that exits with a non-zero status is an error, and its output is included in the error
+  pub fn run(&self, hook: Hook, dotfiles: &PathBuf, package: &krepo::Package, install_dir: &PathBuf)
+             -> super::Result<()> {
+    let command = match self.get(hook) {
+      Some(command) => command,
+      None => return Ok(()),
+    };
+    let dir = match package.dir.is_dir() {
+      true => &package.dir,
+      false => dotfiles,
+    };
+    println!("Running {} hook: {}", hook, command);
+    let output = Command::new("sh")
+        .args(["-c", command])
+        .current_dir(dir)
+        .env("KOT_DOTFILES", dotfiles)
+        .env("KOT_PACKAGE", package.name.as_ref().map_or("", |name| name.as_str()))
+        .env("KOT_INSTALL_DIR", install_dir)
+        .env("KOT_HOOK", hook.to_string())
+        .stdin(Stdio::null())
+        .output()?;
+
+    // Output is captured, so it is printed beneath the hook that produced it
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for line in stdout.lines().chain(stderr.lines()) {
+      println!("  | {}", line);
+    }
+    if !output.status.success() {
+      err!(
+        ErrorKind::HookError(format!("{} hook {:?} failed ({})", hook, command, output.status)),
+        stderr.trim().to_owned()
+      );
+    }
+    return Ok(());
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::kfs;
+
+  #[test]
+  fn hooks_run_within_package() {
+    let dir = kfs::get_test_dir("khook");
+    kfs::create_dir_all(&dir.join("dot/vim")).unwrap();
+    let hooks: Hooks = toml::from_str(concat!(
+      "post-install = \"echo $KOT_PACKAGE $KOT_HOOK $KOT_INSTALL_DIR > hooked\"\n",
+      "pre-uninstall = \"echo failed >&2 && exit 3\"\n",
+    )).unwrap();
+    let package = krepo::Package {
+      name: Some("vim".to_owned()),
+      dir: dir.join("dot/vim"),
+      targets: Default::default(),
+      hooks: hooks.to_owned(),
+      profiles: None,
+    };
+    let (dotfiles, install_dir) = (dir.join("dot"), dir.join("home"));
+    hooks.run(Hook::PreInstall, &dotfiles, &package, &install_dir).unwrap();
+    hooks.run(Hook::PostInstall, &dotfiles, &package, &install_dir).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.join("dot/vim/hooked")).unwrap(),
+               format!("vim post-install {}\n", install_dir.display()));
+
+    let error = hooks.run(Hook::PreUninstall, &dotfiles, &package, &install_dir).unwrap_err();
+    assert!(matches!(&error.kind, ErrorKind::HookError(message) if message.contains("pre-uninstall")),
+            "{}", error);
+    assert!(toml::from_str::<Hooks>("pre-instal = \"true\"").is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,187 @@
+/*##############################################################################
+## Author: Shaun Reed                                                         ##
+## Legal: All Content (c) 2021 Shaun Reed, all rights reserved                ##
+## About: Support for .kotignore files within dotfiles installed by kot       ##
+##                                                                            ##
+## Contact: shaunrd0@gmail.com  | URL: www.shaunreed.com | GitHub: shaunrd0   ##
+##############################################################################*/
+
+use std::path::Path;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+// =============================================================================
+// STRUCTS
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+/// Name of the files within dotfiles that list configurations kot should not install
+pub const KOTIGNORE: &str = ".kotignore";
+
+/// Files at the root of the dotfiles that are not installed unless --include-repo-files is set
+/// + VCS metadata would turn the install directory into a worktree of the dotfiles repository
+pub const REPO_FILES: [&str; 11] = [
+  "/.git", "/.gitignore", "/.gitmodules", "/.gitattributes",
+  "/.hg", "/.hgignore", "/.svn", "/.bzr",
+  "/README*", "/LICENSE*", "/COPYING*",
+];
+
+/// Patterns from each .kotignore between the root of the dotfiles and the current directory
+/// + Patterns use gitignore syntax, and are relative to the directory containing the .kotignore
+/// + Patterns in deeper .kotignore files take precedence, including negated patterns
+#[derive(Clone, Default)]
+pub struct Ignore {
+  matchers: Vec<Gitignore>,
+}
+
+// =============================================================================
+// IMPLEMENTATION
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+impl Ignore {
+  /// Creates the patterns used at the root of the dotfiles
+  /// + The kot configuration file and manifest at the root of the dotfiles are always ignored
+  /// + The profiles directory listed within kot.toml is always ignored, if the dotfiles list one
+  /// + Unless include_repo_files is set, REPO_FILES at the root of the dotfiles are ignored
+  /// + A .kotignore at the root of the dotfiles can still include these files using negated patterns
+  pub fn new(dotfiles: &Path, include_repo_files: bool, profiles: Option<&Path>) -> super::Result<Ignore> {
+    let mut builder = GitignoreBuilder::new(dotfiles);
+    builder.add_line(None, &format!("/{}", super::kconfig::KOTRC))?;
+    builder.add_line(None, &format!("/{}", super::krepo::KOT_TOML))?;
+    if let Some(profiles) = profiles.and_then(|profiles| profiles.strip_prefix(dotfiles).ok()) {
+      builder.add_line(None, &format!("/{}/", profiles.display()))?;
+    }
+    if !include_repo_files {
+      for pattern in REPO_FILES.iter() {
+        builder.add_line(None, pattern)?;
+      }
+    }
+    return Ok(Ignore { matchers: vec![builder.build()?] });
+  }
+
+  /// Returns a copy of these patterns with the .kotignore within dir added, if it exists
+  pub fn with_dir(&self, dir: &Path) -> super::Result<Ignore> {
+    let mut ignore = self.to_owned();
+    let kotignore = dir.join(KOTIGNORE);
+    if kotignore.is_file() {
+      let (matcher, err) = Gitignore::new(&kotignore);
+      if let Some(e) = err {
+        return Err(e.into());
+      }
+      ignore.matchers.push(matcher);
+    }
+    return Ok(ignore);
+  }
+
+  /// Returns a copy of these patterns with each .kotignore between root and path added
+  /// + The .kotignore within path itself is not added
+  pub fn with_parents(&self, root: &Path, path: &Path) -> super::Result<Ignore> {
+    let mut ignore = self.to_owned();
+    if let Ok(relative) = path.strip_prefix(root) {
+      for dir in relative.ancestors().skip(1).collect::<Vec<_>>().into_iter().rev() {
+        ignore = ignore.with_dir(&root.join(dir))?;
+      }
+    }
+    return Ok(ignore);
+  }
+
+  /// Checks if a file or directory within the dotfiles should not be installed
+  /// + .kotignore files themselves are never installed
+  pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+    if path.file_name().is_some_and(|name| name == KOTIGNORE) {
+      return true;
+    }
+    for matcher in self.matchers.iter().rev() {
+      let found = matcher.matched(path, is_dir);
+      if !found.is_none() {
+        return found.is_ignore();
+      }
+    }
+    return false;
+  }
+
+  /// Checks if any file or directory within dir should not be installed
+  /// + Directories containing ignored files can not be linked as a whole
+  pub fn has_ignored(&self, dir: &Path) -> super::Result<bool> {
+    let ignore = self.with_dir(dir)?;
+    for entry in dir.read_dir()? {
+      let path = entry?.path();
+      let is_dir = path.is_dir();
+      if ignore.is_ignored(&path, is_dir) || (is_dir && ignore.has_ignored(&path)?) {
+        return Ok(true);
+      }
+    }
+    return Ok(false);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::kot::kfs::{self, write};
+
+  #[test]
+  fn kotignore_patterns() {
+    let dir = kfs::get_test_dir("kignore-patterns");
+    let dot = dir.join("dot");
+    write(&dot.join(KOTIGNORE), "*.swp\n/notes\nbuild/\n");
+    write(&dot.join(".vimrc"), "vimrc");
+    write(&dot.join(".vimrc.swp"), "swap");
+    write(&dot.join("notes"), "notes");
+    write(&dot.join(".config/notes"), "notes");
+    write(&dot.join(".config/build/out"), "out");
+    write(&dot.join(".config/nvim/.kotignore"), "!init.vim.swp\n");
+    write(&dot.join(".config/nvim/init.vim.swp"), "swap");
+    write(&dot.join(".config/nvim/local.vim.swp"), "swap");
+    write(&dot.join(".local/bin/tool"), "tool");
+
+    let ignore = Ignore::new(&dot, false, None).unwrap().with_dir(&dot).unwrap();
+    assert!(!ignore.is_ignored(&dot.join(".vimrc"), false));
+    assert!(ignore.is_ignored(&dot.join(".vimrc.swp"), false));
+    assert!(ignore.is_ignored(&dot.join(KOTIGNORE), false));
+    // Anchored patterns only match within the directory containing the .kotignore
+    assert!(ignore.is_ignored(&dot.join("notes"), false));
+    assert!(!ignore.is_ignored(&dot.join(".config/notes"), false));
+    assert!(ignore.is_ignored(&dot.join(".config/build"), true));
+
+    // Patterns in deeper .kotignore files take precedence
+    let nvim = dot.join(".config/nvim");
+    let nested = Ignore::new(&dot, false, None).unwrap().with_parents(&dot, &nvim).unwrap().with_dir(&nvim).unwrap();
+    assert!(!nested.is_ignored(&nvim.join("init.vim.swp"), false));
+    assert!(nested.is_ignored(&nvim.join("local.vim.swp"), false));
+    assert!(ignore.has_ignored(&dot.join(".config")).unwrap());
+    assert!(!ignore.has_ignored(&dot.join(".local")).unwrap());
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn repo_files_at_root() {
+    let dir = kfs::get_test_dir("kignore-repo-files");
+    let dot = dir.join("dot");
+    write(&dot.join(".git/HEAD"), "ref: refs/heads/master");
+    write(&dot.join(".gitignore"), "*.swp");
+    write(&dot.join("README.md"), "readme");
+    write(&dot.join(".config/git/.gitignore"), "*.swp");
+    write(&dot.join(".config/README.md"), "readme");
+
+    let ignore = Ignore::new(&dot, false, None).unwrap().with_dir(&dot).unwrap();
+    assert!(ignore.is_ignored(&dot.join(".git"), true));
+    assert!(ignore.is_ignored(&dot.join(".gitignore"), false));
+    assert!(ignore.is_ignored(&dot.join("README.md"), false));
+    // Repository files are only excluded at the root of the dotfiles
+    assert!(!ignore.is_ignored(&dot.join(".config/git/.gitignore"), false));
+    assert!(!ignore.is_ignored(&dot.join(".config/README.md"), false));
+    let included = Ignore::new(&dot, true, None).unwrap().with_dir(&dot).unwrap();
+    assert!(!included.is_ignored(&dot.join(".git"), true));
+    assert!(!included.is_ignored(&dot.join("README.md"), false));
+
+    // A .kotignore at the root can include a single repository file
+    write(&dot.join(KOTIGNORE), "!README.md\n");
+    let ignore = Ignore::new(&dot, false, None).unwrap().with_dir(&dot).unwrap();
+    assert!(!ignore.is_ignored(&dot.join("README.md"), false));
+    assert!(ignore.is_ignored(&dot.join(".gitignore"), false));
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
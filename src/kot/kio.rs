@@ -28,3 +28,9 @@ pub fn prompt(msg: String) -> bool {
     _ => prompt("Please enter Y/y or N/n\n".to_owned()),
   }
 }
+
+/// Asks user for a passphrase without echoing it to the terminal
+/// + Prompt output defined by msg parameter String
+pub fn prompt_passphrase(msg: String) -> io::Result<String> {
+  return rpassword::prompt_password(msg);
+}
@@ -0,0 +1,252 @@
+/*##############################################################################
+## Author: Shaun Reed                                                         ##
+## Legal: All Content (c) 2021 Shaun Reed, all rights reserved                ##
+## About: Write-ahead journal used to recover interrupted installations       ##
+##                                                                            ##
+## Contact: shaunrd0@gmail.com  | URL: www.shaunreed.com | GitHub: shaunrd0   ##
+##############################################################################*/
+
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+
+use crate::kot::err;
+use crate::kot::kerror::{Error, ErrorKind};
+use super::kcli;
+use super::kfs;
+use super::kmanifest;
+use super::kplan;
+
+// =============================================================================
+// STRUCTS
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+/// An installation that is in progress, along with each action it will apply
+/// + Stored as TOML within the kot data directory, see get_journal_path()
+/// + Saved before the first action is applied, so a journal left behind means kot was interrupted
+/// + The step being applied is saved separately before each action, see get_step_path()
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Journal {
+  /// Name of the dotfiles being installed; Matches the prefix of the backup directory
+  pub name: String,
+  /// Name of the package being installed, if the dotfiles are not installed as a whole
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub package: Option<String>,
+  /// Dotfiles as they were provided to the CLI, either a system path or repository URL
+  pub dotfiles: PathBuf,
+  /// Local directory containing the configurations being installed
+  pub source: PathBuf,
+  /// Hash of the commit checked out within source, if the dotfiles were cloned from a repository URL
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub commit: Option<String>,
+  /// Directory the configurations are being installed into
+  pub install_dir: PathBuf,
+  /// Directory used to store backups of conflicting configurations
+  pub backup_dir: PathBuf,
+  /// Local time the installation was started, formatted as %Y-%m-%dT%H:%M:%S
+  pub timestamp: String,
+  /// Index of the action being applied; Every action before it has been applied
+  /// + Equal to the number of actions once every action has been applied
+  /// + Read from the step file when it exists, since it is updated more often than the journal
+  #[serde(default)]
+  pub step: usize,
+  /// Each action planned for the installation, in the order they are applied
+  pub actions: Vec<kplan::Action>,
+}
+
+// =============================================================================
+// IMPLEMENTATION
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+/// Constructs a new PathBuf pointing to the journal of the installation in progress
+pub fn get_journal_path() -> super::Result<PathBuf> {
+  let mut journal_path = kfs::get_data_dir()?;
+  journal_path.push("journal.toml");
+  return Ok(journal_path);
+}
+
+/// Constructs a new PathBuf pointing to the step of the installation in progress
+/// + Only the index of the step is written before each action, so large installations are not written repeatedly
+pub fn get_step_path() -> super::Result<PathBuf> {
+  let mut step_path = kfs::get_data_dir()?;
+  step_path.push("journal.step");
+  return Ok(step_path);
+}
+
+/// Writes contents to path, using a temporary file that is renamed so an interrupted write leaves nothing partial
+fn write_atomic(path: &PathBuf, contents: &str) -> super::Result<()> {
+  let mut temp_path = path.to_owned().into_os_string();
+  temp_path.push(".tmp");
+  std::fs::write(&temp_path, contents)?;
+  std::fs::rename(&temp_path, path)?;
+  return Ok(());
+}
+
+impl Journal {
+  /// Creates a journal for installing the planned actions for a package from dotfiles
+  pub fn new(args: &kcli::Cli, dotfiles: &PathBuf, package: Option<&String>,
+             backup_dir: &PathBuf, actions: Vec<kplan::Action>) -> super::Result<Journal> {
+    return Ok(Journal {
+      name: args.get_name()?,
+      package: package.cloned(),
+      dotfiles: args.dotfiles.as_ref().unwrap().to_owned(),
+      source: dotfiles.to_owned(),
+      commit: args.commit.to_owned(),
+      install_dir: args.install_dir.to_owned(),
+      backup_dir: backup_dir.to_owned(),
+      timestamp: args.timestamp.to_owned(),
+      step: 0,
+      actions,
+    });
+  }
+
+  /// Reads the journal left behind by an interrupted installation
+  /// + If no installation was interrupted, None is returned
+  pub fn load() -> super::Result<Option<Journal>> {
+    let journal_path = get_journal_path()?;
+    if !journal_path.exists() {
+      return Ok(None);
+    }
+    let contents = std::fs::read_to_string(&journal_path)?;
+    let mut journal: Journal = toml::from_str(&contents)?;
+    let step_path = get_step_path()?;
+    if step_path.exists() {
+      journal.step = match std::fs::read_to_string(&step_path)?.trim().parse() {
+        Ok(step) => step,
+        Err(_) => err!(
+          ErrorKind::ConfigError(format!("Unable to read the step of the interrupted installation from {:?}",
+                                         step_path)),
+          format!("Remove {:?} to recover the installation from its first step", step_path)
+        ),
+      };
+    }
+    return Ok(Some(journal));
+  }
+
+  /// Writes the journal and its step to the kot data directory
+  /// + Each file is written to a temporary file first and then renamed,
+  ///     so an interrupted write never leaves a truncated journal behind
+  pub fn save(&self) -> super::Result<()> {
+    kfs::create_dir_all(&kfs::get_data_dir()?)?;
+    write_atomic(&get_step_path()?, &self.step.to_string())?;
+    write_atomic(&get_journal_path()?, &toml::to_string(self)?)?;
+    return Ok(());
+  }
+
+  /// Records that the action at step is about to be applied
+  /// + The journal is saved before the first action, and only the step is written before each later action
+  pub fn begin(&mut self, step: usize) -> super::Result<()> {
+    self.step = step;
+    if !get_journal_path()?.exists() {
+      return self.save();
+    }
+    return write_atomic(&get_step_path()?, &step.to_string());
+  }
+
+  /// Removes the journal and its step once an installation has been completed or rolled back
+  pub fn finish() -> super::Result<()> {
+    for path in [get_journal_path()?, get_step_path()?].iter() {
+      if path.exists() {
+        std::fs::remove_file(path)?;
+      }
+    }
+    return Ok(());
+  }
+
+  /// Constructs the record of this installation to store within the kot manifest
+  /// + Conflicts backed up during installation are recorded with the path to their backup
+  pub fn to_install(&self) -> kmanifest::Install {
+    let mut dirs = vec![];
+    let mut links = vec![];
+    for action in self.actions.iter() {
+      match action {
+        kplan::Action::Mkdir { path } => dirs.push(path.to_owned()),
+        kplan::Action::Link { config, target, mode }
+        | kplan::Action::Skip { config, target, mode } => {
+          links.push(kmanifest::Link {
+            config: config.to_owned(),
+            target: target.to_owned(),
+            mode: *mode,
+            backup: self.actions.iter().find_map(|backup| match backup {
+              kplan::Action::Backup { target: backed_up, backup } if backed_up == target => {
+                Some(backup.to_owned())
+              },
+              _ => None,
+            }),
+          });
+        },
+        _ => { },
+      }
+    }
+    // Sort links so the manifest is stable across runs
+    links.sort_by(|a, b| a.target.cmp(&b.target));
+
+    return kmanifest::Install {
+      name: self.name.to_owned(),
+      package: self.package.to_owned(),
+      dotfiles: self.dotfiles.to_owned(),
+      source: self.source.to_owned(),
+      commit: self.commit.to_owned(),
+      install_dir: self.install_dir.to_owned(),
+      backup_dir: self.backup_dir.to_owned(),
+      timestamp: self.timestamp.to_owned(),
+      dirs,
+      links,
+    };
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn journal_steps_are_saved() {
+    let dir = kfs::get_test_dir("kjournal");
+    let (dot, home) = (dir.join("dot"), dir.join("home"));
+    let mode = kfs::InstallMode::Symlink;
+    let mut journal = Journal {
+      name: "dot".to_owned(),
+      package: None,
+      dotfiles: dot.to_owned(),
+      source: dot.to_owned(),
+      commit: None,
+      install_dir: home.to_owned(),
+      backup_dir: dir.join("backup"),
+      timestamp: "2021-01-01T00:00:00".to_owned(),
+      step: 0,
+      actions: vec![
+        kplan::Action::Mkdir { path: home.join(".config") },
+        kplan::Action::Backup { target: home.join(".bashrc"), backup: dir.join("backup/.bashrc") },
+        kplan::Action::Link { config: dot.join(".bashrc"), target: home.join(".bashrc"), mode },
+        kplan::Action::Link { config: dot.join(".config/nvim"), target: home.join(".config/nvim"), mode },
+      ],
+    };
+    assert!(Journal::load().unwrap().is_none());
+
+    // Only the step is written once the journal is saved by the first step
+    journal.begin(0).unwrap();
+    journal.actions.clear();
+    journal.begin(2).unwrap();
+    let loaded = Journal::load().unwrap().unwrap();
+    assert_eq!(loaded.step, 2);
+    assert_eq!(loaded.actions.len(), 4);
+
+    let install = loaded.to_install();
+    assert_eq!(install.dirs, [home.join(".config")]);
+    let links: Vec<_> = install.links.iter().map(|link| (link.target.to_owned(), link.backup.to_owned())).collect();
+    assert_eq!(links, [
+      (home.join(".bashrc"), Some(dir.join("backup/.bashrc"))),
+      (home.join(".config/nvim"), None),
+    ]);
+
+    std::fs::write(get_step_path().unwrap(), "interrupted").unwrap();
+    assert!(Journal::load().is_err());
+    Journal::finish().unwrap();
+    assert!(Journal::load().unwrap().is_none());
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,241 @@
+/*##############################################################################
+## Author: Shaun Reed                                                         ##
+## Legal: All Content (c) 2021 Shaun Reed, all rights reserved                ##
+## About: Persistent record of installations performed by kot                 ##
+##                                                                            ##
+## Contact: shaunrd0@gmail.com  | URL: www.shaunreed.com | GitHub: shaunrd0   ##
+##############################################################################*/
+
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+use super::kfs;
+
+// =============================================================================
+// STRUCTS
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+/// Collection of every installation kot has performed for this user
+/// + Stored as TOML within the kot data directory, see get_manifest_path()
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+  #[serde(default)]
+  pub installs: Vec<Install>,
+}
+
+/// A single installation of dotfiles into an install directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Install {
+  /// Name of the dotfiles installed; Matches the prefix of the backup directory
+  pub name: String,
+  /// Name of the package installed from the dotfiles, if the dotfiles were not installed as a whole
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub package: Option<String>,
+  /// Dotfiles as they were provided to the CLI, either a system path or repository URL
+  pub dotfiles: PathBuf,
+  /// Local directory containing the configurations that were installed
+  pub source: PathBuf,
+  /// Hash of the commit checked out within source, if the dotfiles were cloned from a repository URL
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub commit: Option<String>,
+  /// Directory the configurations were installed into
+  pub install_dir: PathBuf,
+  /// Directory used to store backups of conflicting configurations
+  pub backup_dir: PathBuf,
+  /// Local time the installation was performed, formatted as %Y-%m-%dT%H:%M:%S
+  pub timestamp: String,
+  /// Directories that were created within the install directory to hold configurations
+  #[serde(default)]
+  pub dirs: Vec<PathBuf>,
+  /// Each configuration that was linked into the install directory
+  #[serde(default)]
+  pub links: Vec<Link>,
+}
+
+/// A configuration linked into the install directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Link {
+  /// Full path to the configuration within the dotfiles
+  pub config: PathBuf,
+  /// Full path to the link created within the install directory
+  pub target: PathBuf,
+  /// Method used to install the configuration at target
+  #[serde(default)]
+  pub mode: kfs::InstallMode,
+  /// If a configuration existed at target, the location it was backed up to
+  #[serde(default)]
+  pub backup: Option<PathBuf>,
+}
+
+// =============================================================================
+// IMPLEMENTATION
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+impl Install {
+  /// Checks if the dotfiles provided to the CLI refer to this installation
+  /// + Dotfiles can be referred to by name, by system path, or by repository URL
+  pub fn matches(&self, dotfiles: &PathBuf) -> bool {
+    if &self.dotfiles == dotfiles || dotfiles.to_str() == Some(&self.name) {
+      return true;
+    }
+    // Relative system paths are compared using their absolute path
+    return match dotfiles.canonicalize() {
+      Ok(path) => path == self.dotfiles || path == self.source,
+      Err(_) => false,
+    };
+  }
+}
+
+/// Constructs a new PathBuf pointing to the manifest of installations made by kot
+pub fn get_manifest_path() -> super::Result<PathBuf> {
+  let mut manifest_path = kfs::get_data_dir()?;
+  manifest_path.push("manifest.toml");
+  return Ok(manifest_path);
+}
+
+impl Manifest {
+  /// Reads the manifest from the kot data directory
+  /// + If no manifest exists yet, an empty manifest is returned
+  pub fn load() -> super::Result<Manifest> {
+    let manifest_path = get_manifest_path()?;
+    if !manifest_path.exists() {
+      return Ok(Manifest::default());
+    }
+    let contents = std::fs::read_to_string(&manifest_path)?;
+    return Ok(toml::from_str(&contents)?);
+  }
+
+  /// Writes the manifest to the kot data directory
+  /// + The manifest is written to a temporary file first and then renamed,
+  ///     so an interrupted write never leaves a truncated manifest behind
+  pub fn save(&self) -> super::Result<()> {
+    let manifest_path = get_manifest_path()?;
+    kfs::create_dir_all(&kfs::get_data_dir()?)?;
+    let mut temp_path = manifest_path.to_owned();
+    temp_path.set_extension("toml.tmp");
+    std::fs::write(&temp_path, toml::to_string(self)?)?;
+    std::fs::rename(&temp_path, &manifest_path)?;
+    return Ok(());
+  }
+
+  /// Finds the index of the most recent installation into install_dir
+  /// + If dotfiles is provided, only installations of those dotfiles are considered
+  pub fn find_latest(&self, install_dir: &PathBuf, dotfiles: Option<&PathBuf>)
+                     -> Option<usize> {
+    return self.installs.iter()
+        .rposition(|install| {
+          &install.install_dir == install_dir
+              && dotfiles.is_none_or(|d| install.matches(d))
+        });
+  }
+
+  /// Finds the index of the installation of a package from source into install_dir
+  pub fn find_package(&self, install_dir: &PathBuf, source: &PathBuf, package: Option<&String>)
+                      -> Option<usize> {
+    return self.installs.iter()
+        .rposition(|install| {
+          &install.install_dir == install_dir && &install.source == source
+              && install.package.as_ref() == package
+        });
+  }
+
+  /// Finds the index of each installed package from the dotfiles most recently installed into install_dir
+  /// + If dotfiles is provided, only installations of those dotfiles are considered
+  /// + If packages are provided, only installations of those packages are returned
+  pub fn find_installs(&self, install_dir: &PathBuf, dotfiles: Option<&PathBuf>,
+                       packages: &[String]) -> Vec<usize> {
+    let selected = |install: &Install| {
+      packages.is_empty() || install.package.as_ref().is_some_and(|p| packages.contains(p))
+    };
+    let latest = self.installs.iter()
+        .rposition(|install| {
+          &install.install_dir == install_dir && selected(install)
+              && dotfiles.is_none_or(|d| install.matches(d))
+        });
+    let source = match latest {
+      Some(index) => &self.installs[index].source,
+      None => return vec![],
+    };
+    return (0..self.installs.len())
+        .filter(|i| {
+          let install = &self.installs[*i];
+          &install.install_dir == install_dir && &install.source == source && selected(install)
+        })
+        .collect();
+  }
+
+  /// Finds the most recent link to target that is still installed from the dotfiles
+  /// + Returns the index of the installation and the index of the link within it
+  pub fn find_link(&self, target: &PathBuf) -> Option<(usize, usize)> {
+    for (i, install) in self.installs.iter().enumerate().rev() {
+      for (j, link) in install.links.iter().enumerate() {
+        if &link.target == target && kfs::is_installed(&link.config, &link.target, link.mode) {
+          return Some((i, j));
+        }
+      }
+    }
+    return None;
+  }
+
+  /// Finds the target a backup was moved from, using the backup recorded for each link
+  /// + Paths within a backed up directory are found at the same path within its target
+  /// + Returns None if backup_path is not within any recorded backup
+  pub fn find_backup_target(&self, backup_path: &Path) -> Option<PathBuf> {
+    for install in self.installs.iter().rev() {
+      for link in install.links.iter() {
+        let relative = match link.backup.as_ref().and_then(|backup| backup_path.strip_prefix(backup).ok()) {
+          Some(relative) => relative,
+          None => continue,
+        };
+        return match relative.as_os_str().is_empty() {
+          true => Some(link.target.to_owned()),
+          false => Some(link.target.join(relative)),
+        };
+      }
+    }
+    return None;
+  }
+
+  /// Checks if the backup recorded for any link is within dir, excluding dir itself
+  pub fn has_backups_within(&self, dir: &Path) -> bool {
+    return self.installs.iter()
+        .flat_map(|install| install.links.iter())
+        .any(|link| link.backup.as_ref().is_some_and(|backup| backup.starts_with(dir) && backup != dir));
+  }
+
+  /// Removes a link from an installation
+  /// + Installations with no remaining links are removed from the manifest
+  pub fn remove_link(&mut self, install: usize, link: usize) {
+    self.installs[install].links.remove(link);
+    if self.installs[install].links.is_empty() {
+      self.installs.remove(install);
+    }
+  }
+
+  /// Adds a new installation to the manifest and saves it
+  /// + A previous installation of the same package into the same directory is replaced
+  /// + Backups and directories recorded by the previous installation are carried over
+  pub fn record(mut install: Install) -> super::Result<()> {
+    let mut manifest = Manifest::load()?;
+    if let Some(index) = manifest.find_package(&install.install_dir, &install.source,
+                                               install.package.as_ref()) {
+      let previous = manifest.installs.remove(index);
+      for dir in previous.dirs.into_iter().filter(|dir| dir.is_dir()) {
+        if !install.dirs.contains(&dir) {
+          install.dirs.push(dir);
+        }
+      }
+      for link in install.links.iter_mut().filter(|link| link.backup.is_none()) {
+        link.backup = previous.links.iter()
+            .find(|old| old.target == link.target && old.config == link.config)
+            .and_then(|old| old.backup.to_owned());
+      }
+    }
+    manifest.installs.push(install);
+    return manifest.save();
+  }
+}
@@ -0,0 +1,392 @@
+/*##############################################################################
+## Author: Shaun Reed                                                         ##
+## Legal: All Content (c) 2021 Shaun Reed, all rights reserved                ##
+## About: Plans the filesystem changes made when installing dotfiles          ##
+##                                                                            ##
+## Contact: shaunrd0@gmail.com  | URL: www.shaunreed.com | GitHub: shaunrd0   ##
+##############################################################################*/
+
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+use super::kcli;
+use super::kfs;
+use super::kignore;
+use super::kmanifest;
+use super::krepo;
+use super::ksecret;
+use super::ktemplate;
+
+// =============================================================================
+// STRUCTS
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+/// A single change to the filesystem made while installing dotfiles
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum Action {
+  /// Removes a configuration installed by a previous installation that is no longer in the dotfiles
+  /// + If the previous installation backed up a file at target, the backup is moved back into place
+  Remove { config: PathBuf, target: PathBuf, mode: kfs::InstallMode, backup: Option<PathBuf> },
+  /// Creates a directory within the install directory to hold configurations
+  Mkdir { path: PathBuf },
+  /// Moves a conflicting file or directory into the backup directory
+  Backup { target: PathBuf, backup: PathBuf },
+  /// Installs a configuration at target
+  Link { config: PathBuf, target: PathBuf, mode: kfs::InstallMode },
+  /// Writes the rendered contents of a template to config, which is installed in place of the template
+  /// + created is set if config did not exist when the installation was planned, so a rollback removes it
+  Render {
+    template: PathBuf,
+    config: PathBuf,
+    contents: String,
+    #[serde(default)]
+    created: bool,
+  },
+  /// Writes the decrypted contents of a secret to config, which is installed in place of the secret
+  /// + Decrypted contents are never written to the journal, so a recovered installation decrypts the secret again
+  /// + created is set if config did not exist when the installation was planned, so a rollback removes it
+  Decrypt {
+    secret: PathBuf,
+    config: PathBuf,
+    #[serde(skip)]
+    contents: Option<Vec<u8>>,
+    #[serde(default)]
+    created: bool,
+  },
+  /// Leaves a configuration that is already installed at target
+  Skip { config: PathBuf, target: PathBuf, mode: kfs::InstallMode },
+}
+
+// =============================================================================
+// IMPLEMENTATION
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+impl std::fmt::Display for Action {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      Action::Remove { target, backup: None, .. } => write!(f, "{:<7} {:?}", "remove", target),
+      Action::Remove { target, backup: Some(backup), .. } =>
+        write!(f, "{:<7} {:?} (restores {:?})", "remove", target, backup),
+      Action::Mkdir { path } => write!(f, "{:<7} {:?}", "mkdir", path),
+      Action::Backup { target, backup } =>
+        write!(f, "{:<7} {:?} -> {:?}", "backup", target, backup),
+      Action::Link { config, target, mode } =>
+        write!(f, "{:<7} {:?} -> {:?} ({})", "link", target, config, mode),
+      Action::Render { template, config, .. } =>
+        write!(f, "{:<7} {:?} -> {:?}", "render", template, config),
+      Action::Decrypt { secret, config, .. } =>
+        write!(f, "{:<7} {:?} -> {:?}", "decrypt", secret, config),
+      Action::Skip { target, .. } =>
+        write!(f, "{:<7} {:?} (already installed)", "skip", target),
+    }
+  }
+}
+
+/// Returns the path each configuration within a package is installed at
+/// + Reads kot.toml and .kotignore files within the dotfiles, see kfs::get_target_paths()
+/// + The package within each profile is installed on top of the package within the dotfiles, see kfs::Layer
+pub fn get_config_map(args: &kcli::Cli, dotfiles: &PathBuf, package: &krepo::Package)
+                      -> super::Result<kfs::HashMap<PathBuf, PathBuf>> {
+  let mut layers = vec![kfs::Layer {
+    dir: package.dir.to_owned(),
+    ignore: kignore::Ignore::new(dotfiles, args.include_repo_files, package.profiles.as_deref())?
+        .with_parents(dotfiles, &package.dir)?,
+  }];
+  for profile in args.get_profiles(package.profiles.as_ref())? {
+    let profile_dir = package.profiles.as_ref().unwrap().join(profile);
+    let layer = kfs::Layer { dir: profile_dir.to_owned(), ignore: kignore::Ignore::new(&profile_dir, true, None)? };
+    layers.push(layer.join(package.dir.strip_prefix(dotfiles).unwrap())?);
+  }
+  let targets = package.get_targets(&args.install_dir)?;
+  return kfs::get_target_paths(&args.install_dir, &layers, args.dot_prefix, &targets);
+}
+
+/// Returns the file a configuration within a package is installed from
+/// + Templates are installed from the file they are rendered to, see ktemplate::get_rendered_path()
+/// + Secrets are installed from the file they are decrypted to, see ksecret::get_decrypted_path()
+/// + name is the name of the dotfiles, as recorded within the kot manifest
+pub fn get_install_source(name: &String, dotfiles: &Path, package: &krepo::Package,
+                          config_path: &PathBuf) -> super::Result<PathBuf> {
+  let dir_name = name.to_owned()
+      + &package.name.as_ref().map_or("".to_owned(), |package| ".".to_owned() + package);
+  if ktemplate::is_template(config_path) {
+    return Ok(ktemplate::get_rendered_path(&ktemplate::get_rendered_root()?.join(dir_name), dotfiles, config_path));
+  }
+  if ksecret::is_secret(config_path) {
+    return Ok(ksecret::get_decrypted_path(&ksecret::get_secrets_root()?.join(dir_name), dotfiles, config_path));
+  }
+  return Ok(config_path.to_owned());
+}
+
+/// Computes every action needed to install a package from dotfiles into the install directory
+/// + Links recorded within manifest by a previous installation of the same package that are no longer planned are removed
+/// + Templates are rendered and secrets are decrypted on each installation, and stale copies of them are replaced
+/// + Conflicting files at a target are backed up within backup_dir before the configuration is installed
+/// + This function does not create or modify any files or directories
+pub fn plan_install(args: &kcli::Cli, manifest: &kmanifest::Manifest, dotfiles: &PathBuf,
+                    package: &krepo::Package, backup_dir: &Path) -> super::Result<Vec<Action>> {
+  // Templates are rendered and secrets are decrypted now, so errors are reported before anything is changed
+  // + Variables are only read once a template is found, so dotfiles without templates never require them
+  let mut vars = None;
+  let name = args.get_name()?;
+  let mut renders = kfs::HashMap::new();
+  let mut config_map = kfs::HashMap::new();
+  for (config_path, target_path) in get_config_map(args, dotfiles, package)? {
+    let source = get_install_source(&name, dotfiles, package, &config_path)?;
+    if ktemplate::is_template(&config_path) {
+      if vars.is_none() {
+        vars = Some(ktemplate::get_vars(&args.get_profiles(package.profiles.as_ref())?, &args.vars)?);
+      }
+      renders.insert(source.to_owned(), Action::Render {
+        contents: ktemplate::render(&config_path, vars.as_ref().unwrap())?,
+        template: config_path.to_owned(),
+        config: source.to_owned(),
+        created: source.symlink_metadata().is_err(),
+      });
+    }
+    else if ksecret::is_secret(&config_path) {
+      renders.insert(source.to_owned(), Action::Decrypt {
+        contents: Some(ksecret::decrypt(&config_path)?),
+        secret: config_path.to_owned(),
+        config: source.to_owned(),
+        created: source.symlink_metadata().is_err(),
+      });
+    }
+    let mode = args.get_mode(dotfiles, package, &config_path);
+    config_map.insert(source, (target_path, mode));
+  }
+  let mut configs: Vec<_> = config_map.iter().collect();
+  configs.sort_by(|a, b| (a.1).0.cmp(&(b.1).0));
+
+  let mut actions = vec![];
+  let mut removed = vec![];
+  // Remove configurations we installed previously that are no longer in the dotfiles
+  // + Configurations installed using a different mode are also removed and installed again
+  if let Some(index) = manifest.find_package(&args.install_dir, dotfiles, package.name.as_ref()) {
+    for link in manifest.installs[index].links.iter() {
+      let planned = config_map.get(&link.config) == Some(&(link.target.to_owned(), link.mode));
+      if !planned && kfs::is_installed(&link.config, &link.target, link.mode) {
+        // Restored backups at target will be a conflict, so target is not considered free
+        if link.backup.is_none() {
+          removed.push(link.target.to_owned());
+        }
+        actions.push(Action::Remove {
+          config: link.config.to_owned(),
+          target: link.target.to_owned(),
+          mode: link.mode,
+          backup: link.backup.to_owned(),
+        });
+      }
+    }
+  }
+
+  for (config_path, (target_path, mode)) in configs {
+    let config = config_path.to_owned();
+    let target = target_path.to_owned();
+    let mode = *mode;
+    let render = renders.remove(config_path);
+    // Copies of a template or secret are stale if its contents differ from the copy installed
+    let copied = mode == kfs::InstallMode::Copy || mode == kfs::InstallMode::Hardlink;
+    let stale = copied && match &render {
+      Some(Action::Render { contents, .. }) =>
+        std::fs::read_to_string(config_path).ok().as_ref() != Some(contents),
+      Some(Action::Decrypt { contents, .. }) => std::fs::read(config_path).ok() != *contents,
+      _ => false,
+    };
+    let removing = actions.iter()
+        .any(|action| matches!(action, Action::Remove { target, .. } if target == target_path));
+    // Directories we linked previously may be unfolded, such as when a profile adds to them
+    // + Targets within a link that is removed are installed within the directory created in its place
+    let unfolding = removed.iter().any(|path| target_path.starts_with(path) && target_path != path);
+    if !removing && !unfolding && kfs::is_installed(config_path, target_path, mode) {
+      match kfs::get_link_mode(target_path) {
+        // Links using the other form are replaced by a link using the form requested
+        Some(link_mode) if link_mode != mode => {
+          removed.push(target.to_owned());
+          actions.push(Action::Remove {
+            config: config.to_owned(),
+            target: target.to_owned(),
+            mode: link_mode,
+            backup: None,
+          });
+        },
+        _ if stale => {
+          removed.push(target.to_owned());
+          actions.push(Action::Remove {
+            config: config.to_owned(),
+            target: target.to_owned(),
+            mode,
+            backup: None,
+          });
+        },
+        _ => {
+          actions.extend(render);
+          actions.push(Action::Skip { config, target, mode });
+          continue;
+        },
+      }
+    }
+
+    // Directories containing ignored files are created instead of being linked as a whole
+    for dir in target_path.ancestors().skip(1).collect::<Vec<_>>().into_iter().rev() {
+      let path = dir.to_path_buf();
+      let mkdir = Action::Mkdir { path: path.to_owned() };
+      let replaced = removed.iter().any(|removed| path.starts_with(removed));
+      if (path.symlink_metadata().is_err() || replaced) && !actions.contains(&mkdir) {
+        actions.push(mkdir);
+      }
+    }
+    // Anything at the target that isn't our configuration is a conflict, including broken links
+    if target_path.symlink_metadata().is_ok() && !removed.contains(&target) && !unfolding {
+      actions.push(Action::Backup {
+        target: target.to_owned(),
+        backup: kfs::get_backup_path(backup_dir, &args.install_dir, target_path),
+      });
+    }
+    actions.extend(render);
+    actions.push(Action::Link { config, target, mode });
+  }
+  return Ok(actions);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use structopt::StructOpt;
+  use kfs::write;
+
+  /// Plans installing dotfiles into install_dir, using the options provided to the CLI
+  /// + No previous installation is recorded, so configurations already installed are skipped but never removed
+  fn plan(dotfiles: &Path, install_dir: &Path, options: &[&str]) -> Vec<Action> {
+    let mut arguments = vec!["kot"];
+    arguments.extend(options);
+    arguments.push(dotfiles.to_str().unwrap());
+    let mut args = kcli::Cli::from_iter(arguments);
+    args.install_dir = install_dir.to_owned();
+    let dotfiles = dotfiles.to_path_buf();
+    let package = krepo::Repo::load(&dotfiles).unwrap().get_packages(&dotfiles, &[]).unwrap().pop().unwrap();
+    return plan_install(&args, &kmanifest::Manifest::default(), &dotfiles, &package,
+                        &install_dir.with_extension("backup")).unwrap();
+  }
+
+  fn link(config: PathBuf, target: PathBuf) -> Action {
+    return Action::Link { config, target, mode: kfs::InstallMode::Symlink };
+  }
+
+  #[test]
+  fn folds_new_directories() {
+    let dir = kfs::get_test_dir("kplan-fold");
+    let (dot, home) = (dir.join("dot"), dir.join("home"));
+    write(&dot.join(".bashrc"), "bashrc");
+    write(&dot.join(".config/nvim/init.vim"), "init");
+    std::fs::create_dir(&home).unwrap();
+    assert_eq!(plan(&dot, &home, &[]), [
+      link(dot.join(".bashrc"), home.join(".bashrc")),
+      link(dot.join(".config"), home.join(".config")),
+    ]);
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn unfolds_existing_directories() {
+    let dir = kfs::get_test_dir("kplan-unfold");
+    let (dot, home) = (dir.join("dot"), dir.join("home"));
+    write(&dot.join(".bashrc"), "bashrc");
+    write(&dot.join(".vimrc"), "vimrc");
+    write(&dot.join(".config/nvim/init.vim"), "init");
+    write(&home.join(".bashrc"), "conflict");
+    write(&home.join(".config/gtk-3.0/settings.ini"), "gtk");
+    std::os::unix::fs::symlink(dot.join(".vimrc"), home.join(".vimrc")).unwrap();
+    assert_eq!(plan(&dot, &home, &[]), [
+      Action::Backup { target: home.join(".bashrc"), backup: dir.join("home.backup/.bashrc") },
+      link(dot.join(".bashrc"), home.join(".bashrc")),
+      link(dot.join(".config/nvim"), home.join(".config/nvim")),
+      Action::Skip { config: dot.join(".vimrc"), target: home.join(".vimrc"), mode: kfs::InstallMode::Symlink },
+    ]);
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn dot_prefixes() {
+    let dir = kfs::get_test_dir("kplan-dot-prefix");
+    let (dot, home) = (dir.join("dot"), dir.join("home"));
+    write(&dot.join("dot-bashrc"), "bashrc");
+    write(&dot.join("dot-config/nvim/init.vim"), "init");
+    write(&dot.join("dot-vim/dot-netrwhist"), "netrwhist");
+    std::fs::create_dir(&home).unwrap();
+    // Directories containing prefixed names are unfolded, so each name within them is installed with a dot
+    assert_eq!(plan(&dot, &home, &["--dot-prefix"]), [
+      link(dot.join("dot-bashrc"), home.join(".bashrc")),
+      link(dot.join("dot-config"), home.join(".config")),
+      Action::Mkdir { path: home.join(".vim") },
+      link(dot.join("dot-vim/dot-netrwhist"), home.join(".vim/.netrwhist")),
+    ]);
+    assert_eq!(plan(&dot, &home, &[]), [
+      link(dot.join("dot-bashrc"), home.join("dot-bashrc")),
+      link(dot.join("dot-config"), home.join("dot-config")),
+      link(dot.join("dot-vim"), home.join("dot-vim")),
+    ]);
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn kot_toml_targets() {
+    let dir = kfs::get_test_dir("kplan-targets");
+    let (dot, home) = (dir.join("dot"), dir.join("home"));
+    write(&dot.join(krepo::KOT_TOML), &format!(r#"
+      [targets]
+      "nvim/" = "~/.config/nvim"
+      "tmux/tmux.conf" = ".tmux.conf"
+      "app.conf" = "{}"
+    "#, dir.join("etc/app.conf").display()));
+    write(&dot.join("nvim/init.vim"), "init");
+    write(&dot.join("tmux/tmux.conf"), "tmux");
+    write(&dot.join("tmux/plugins/tpm"), "tpm");
+    write(&dot.join("app.conf"), "app");
+    write(&dir.join("etc/app.conf"), "conflict");
+    std::fs::create_dir(&home).unwrap();
+    // Targets outside of the install directory are backed up using their full path
+    let backup = dir.join("home.backup").join(dir.strip_prefix("/").unwrap()).join("etc/app.conf");
+    // Configurations that are not listed are installed at their own path, and kot.toml is never installed
+    assert_eq!(plan(&dot, &home, &[]), [
+      Action::Backup { target: dir.join("etc/app.conf"), backup },
+      link(dot.join("app.conf"), dir.join("etc/app.conf")),
+      Action::Mkdir { path: home.join(".config") },
+      link(dot.join("nvim"), home.join(".config/nvim")),
+      link(dot.join("tmux/tmux.conf"), home.join(".tmux.conf")),
+      Action::Mkdir { path: home.join("tmux") },
+      link(dot.join("tmux/plugins"), home.join("tmux/plugins")),
+    ]);
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn profiles_shadow_dotfiles() {
+    let dir = kfs::get_test_dir("kplan-profiles");
+    let (dot, home) = (dir.join("dot"), dir.join("home"));
+    write(&dot.join(krepo::KOT_TOML), "profiles = \"profiles\"");
+    write(&dot.join(".bashrc"), "bashrc");
+    write(&dot.join(".config/nvim/init.vim"), "init");
+    write(&dot.join("profiles/laptop/.bashrc"), "laptop");
+    write(&dot.join("profiles/laptop/.config/nvim/local.vim"), "local");
+    write(&dot.join("profiles/desktop/.bashrc"), "desktop");
+    std::fs::create_dir(&home).unwrap();
+    assert_eq!(plan(&dot, &home, &["--profile", "laptop", "--file-mode", ".bashrc=copy"]), [
+      Action::Link { config: dot.join("profiles/laptop/.bashrc"), target: home.join(".bashrc"), mode: kfs::InstallMode::Copy },
+      Action::Mkdir { path: home.join(".config") },
+      Action::Mkdir { path: home.join(".config/nvim") },
+      link(dot.join(".config/nvim/init.vim"), home.join(".config/nvim/init.vim")),
+      link(dot.join("profiles/laptop/.config/nvim/local.vim"), home.join(".config/nvim/local.vim")),
+    ]);
+    assert_eq!(plan(&dot, &home, &["--profile", "desktop"]), [
+      link(dot.join("profiles/desktop/.bashrc"), home.join(".bashrc")),
+      link(dot.join(".config"), home.join(".config")),
+    ]);
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,335 @@
+/*##############################################################################
+## Author: Shaun Reed                                                         ##
+## Legal: All Content (c) 2021 Shaun Reed, all rights reserved                ##
+## About: Manifest within dotfiles describing where configurations install    ##
+##                                                                            ##
+## Contact: shaunrd0@gmail.com  | URL: www.shaunreed.com | GitHub: shaunrd0   ##
+##############################################################################*/
+
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use serde::Deserialize;
+
+use crate::kot::err;
+use crate::kot::kerror::{Error, ErrorKind};
+use super::khook;
+
+// =============================================================================
+// STRUCTS
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+/// Name of the manifest kot reads from the root of the dotfiles
+pub const KOT_TOML: &str = "kot.toml";
+
+/// Manifest describing how configurations within the dotfiles are installed
+/// + Configurations that are not listed are installed at the same path within the install directory
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Repo {
+  /// Maps a path relative to the root of the dotfiles to the path it is installed at
+  /// + Targets starting with ~ or relative targets are within the install directory
+  pub targets: BTreeMap<PathBuf, PathBuf>,
+  /// Named sets of configurations that can be installed separately, see PackageConfig
+  /// + If any packages are listed, only configurations within packages are installed
+  pub packages: BTreeMap<String, PackageConfig>,
+  /// Commands run before and after the dotfiles are installed or uninstalled, see khook::Hooks
+  pub hooks: khook::Hooks,
+  /// Directory relative to the root of the dotfiles containing an overlay for each profile
+  /// + profiles/laptop/.bashrc shadows .bashrc within the dotfiles when the laptop profile is installed
+  /// + Dotfiles that do not list a directory have no profiles, and every directory within them is installed
+  pub profiles: Option<PathBuf>,
+}
+
+/// A package listed within kot.toml
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PackageConfig {
+  /// Directory relative to the root of the dotfiles containing the package; Defaults to the package name
+  pub dir: Option<PathBuf>,
+  /// Maps a path relative to the package directory to the path it is installed at
+  pub targets: BTreeMap<PathBuf, PathBuf>,
+  /// Commands run before and after the package is installed or uninstalled
+  pub hooks: khook::Hooks,
+  /// Names of other packages listed within kot.toml that are installed before this package
+  pub depends: Vec<String>,
+}
+
+/// A set of configurations within the dotfiles that are installed together
+/// + Each package is installed, backed up, and recorded separately
+#[derive(Debug, Clone)]
+pub struct Package {
+  /// Name of the package, or None if the dotfiles are installed as a whole
+  pub name: Option<String>,
+  /// Directory whose contents are installed into the install directory
+  pub dir: PathBuf,
+  /// Maps a path relative to dir to the path it is installed at
+  pub targets: BTreeMap<PathBuf, PathBuf>,
+  /// Commands run before and after the package is installed or uninstalled
+  pub hooks: khook::Hooks,
+  /// Full path to the directory containing an overlay for each profile, if the dotfiles list one
+  pub profiles: Option<PathBuf>,
+}
+
+// =============================================================================
+// IMPLEMENTATION
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+/// Expands a target provided within kot.toml to a full path
+/// + A leading ~ refers to the install directory, which is $HOME by default
+/// + Relative targets are relative to the install directory, absolute targets are used as provided
+pub fn expand_target(target: &Path, install_dir: &Path) -> PathBuf {
+  let mut components = target.components();
+  return match components.next() {
+    Some(Component::Normal(home)) if home == "~" => install_dir.join(components.as_path()),
+    _ => install_dir.join(target),
+  };
+}
+
+/// Checks that no target listed within kot.toml contains ..
+/// + Backups mirror the path of each target, so a target containing .. could back up or remove files
+///     outside of the install and backup directories
+fn check_targets(targets: &BTreeMap<PathBuf, PathBuf>, repo_path: &Path) -> super::Result<()> {
+  for (source, target) in targets.iter() {
+    if target.components().any(|c| c == Component::ParentDir) {
+      err!(
+        ErrorKind::ConfigError(format!("Target {:?} for {:?} contains ..", target, source)),
+        format!("Provide the full path to the target within {:?}", repo_path)
+      );
+    }
+  }
+  return Ok(());
+}
+
+impl Repo {
+  /// Reads kot.toml from the root of the dotfiles
+  /// + If the dotfiles do not contain kot.toml, every configuration is installed at its own path
+  /// + Targets containing .. are an error, see check_targets()
+  pub fn load(dotfiles: &Path) -> super::Result<Repo> {
+    let repo_path = dotfiles.join(KOT_TOML);
+    if !repo_path.is_file() {
+      return Ok(Repo::default());
+    }
+    let contents = std::fs::read_to_string(&repo_path)?;
+    let repo: Repo = match toml::from_str(&contents) {
+      Ok(repo) => repo,
+      Err(e) => err!(
+        ErrorKind::ConfigError(e.to_string()),
+        format!("Unable to parse dotfiles manifest: {:?}", repo_path)
+      ),
+    };
+    check_targets(&repo.targets, &repo_path)?;
+    if let Some(profiles) = &repo.profiles {
+      let mut components = profiles.components();
+      if !matches!(components.next(), Some(Component::Normal(_)))
+          || components.any(|c| !matches!(c, Component::Normal(_))) {
+        err!(
+          ErrorKind::ConfigError(format!("Profiles directory {:?} is not within the dotfiles", profiles)),
+          format!("Provide the profiles directory relative to the root of the dotfiles within {:?}", repo_path)
+        );
+      }
+    }
+    for config in repo.packages.values() {
+      check_targets(&config.targets, &repo_path)?;
+    }
+    return Ok(repo);
+  }
+
+  /// Returns the packages within the dotfiles to install
+  /// + If no packages are selected, every package listed within kot.toml is returned
+  /// + If kot.toml does not list any packages, the dotfiles are returned as a single unnamed package
+  /// + Selected packages must be listed within kot.toml, see get_package()
+  /// + Dependencies of selected packages are also returned, and each package follows its dependencies
+  pub fn get_packages(&self, dotfiles: &Path, selected: &[String])
+                      -> super::Result<Vec<Package>> {
+    if !self.packages.is_empty() && (!self.targets.is_empty() || !self.hooks.is_empty()) {
+      err!(
+        ErrorKind::ConfigError("Targets and hooks must be listed within packages".to_owned()),
+        format!("Move the targets and hooks within {:?} into a package", dotfiles.join(KOT_TOML))
+      );
+    }
+    if selected.is_empty() {
+      if self.packages.is_empty() {
+        return Ok(vec![Package {
+          name: None,
+          dir: dotfiles.to_owned(),
+          targets: self.targets.to_owned(),
+          hooks: self.hooks.to_owned(),
+          profiles: self.get_profiles_dir(dotfiles),
+        }]);
+      }
+      return self.resolve_depends(self.packages.keys())?.iter()
+          .map(|name| self.get_package(dotfiles, name)).collect();
+    }
+    return self.resolve_depends(selected.iter())?.iter()
+        .map(|name| self.get_package(dotfiles, name)).collect();
+  }
+
+  /// Orders packages after the packages they depend on, adding any dependencies that were not provided
+  /// + Dependencies must be listed within kot.toml, and packages can not depend on themselves through any dependency
+  fn resolve_depends<'a>(&self, packages: impl Iterator<Item = &'a String>) -> super::Result<Vec<String>> {
+    let mut resolved = vec![];
+    for name in packages {
+      self.visit_depends(name, &mut vec![], &mut resolved)?;
+    }
+    return Ok(resolved);
+  }
+
+  /// Adds the dependencies of a package to resolved in depth-first order, followed by the package itself
+  /// + stack holds the packages currently being visited, so a package found within it is a cycle
+  fn visit_depends(&self, name: &String, stack: &mut Vec<String>, resolved: &mut Vec<String>)
+                   -> super::Result<()> {
+    if resolved.contains(name) {
+      return Ok(());
+    }
+    if let Some(start) = stack.iter().position(|visiting| visiting == name) {
+      let cycle: Vec<_> = stack[start..].iter().chain(std::iter::once(name)).cloned().collect();
+      err!(
+        ErrorKind::ConfigError(format!("Package dependencies form a cycle: {}", cycle.join(" -> "))),
+        format!("Remove one of the dependencies listed within {}", KOT_TOML)
+      );
+    }
+    stack.push(name.to_owned());
+    for depend in self.packages.get(name).map_or(&vec![], |config| &config.depends) {
+      if !self.packages.contains_key(depend) {
+        err!(
+          ErrorKind::ConfigError(format!("Package {:?} depends on {:?}, which is not listed", name, depend)),
+          format!("List each dependency as a package within {}", KOT_TOML)
+        );
+      }
+      self.visit_depends(depend, stack, resolved)?;
+    }
+    stack.pop();
+    resolved.push(name.to_owned());
+    return Ok(());
+  }
+
+  /// Returns the name of each package listed within kot.toml that depends on a package, directly or indirectly
+  /// + Packages whose dependencies can not be resolved are not included
+  pub fn get_dependents(&self, name: &String) -> Vec<String> {
+    return self.packages.keys()
+        .filter(|package| *package != name)
+        .filter(|package| {
+          self.resolve_depends(std::iter::once(*package)).is_ok_and(|depends| depends.contains(name))
+        })
+        .cloned().collect();
+  }
+
+  /// Returns the full path to the directory containing an overlay for each profile, if the dotfiles list one
+  pub fn get_profiles_dir(&self, dotfiles: &Path) -> Option<PathBuf> {
+    return self.profiles.as_ref().map(|profiles| dotfiles.join(profiles));
+  }
+
+  /// Returns a single package listed within kot.toml
+  /// + Dotfiles that do not list any packages are installed as a whole, so they have no packages to select
+  /// + Packages do not need a directory if they only list targets
+  pub fn get_package(&self, dotfiles: &Path, name: &String) -> super::Result<Package> {
+    let config = match self.packages.get(name) {
+      Some(config) => config,
+      None => err!(
+        ErrorKind::ConfigError(format!("No package {:?} within {:?}", name, dotfiles.join(KOT_TOML))),
+        "Packages are listed within kot.toml, such as [packages.vim] for the vim directory".to_owned()
+      ),
+    };
+    let package = Package {
+      name: Some(name.to_owned()),
+      dir: dotfiles.join(config.dir.as_ref().unwrap_or(&PathBuf::from(name))),
+      targets: config.targets.to_owned(),
+      hooks: config.hooks.to_owned(),
+      profiles: self.get_profiles_dir(dotfiles),
+    };
+    if !package.dir.starts_with(dotfiles) || package.dir.components().any(|c| c == Component::ParentDir) {
+      err!(
+        ErrorKind::ConfigError(format!("Package {:?} is not within {:?}", name, dotfiles)),
+        format!("Check the dir listed for the package within {}", KOT_TOML)
+      );
+    }
+    return Ok(package);
+  }
+}
+
+impl Package {
+  /// Returns each configuration listed within targets relative to the package directory, and the path it is installed at
+  /// + Each configuration must be within the package directory, see kfs::get_target_paths()
+  pub fn get_targets(&self, install_dir: &Path) -> super::Result<Vec<(PathBuf, PathBuf)>> {
+    let mut targets = vec![];
+    for (source, target) in self.targets.iter() {
+      if source.is_absolute() || source.components().any(|c| c == Component::ParentDir) {
+        err!(
+          ErrorKind::ConfigError(format!("No configuration {:?} within {:?}", source, self.dir)),
+          "Check the targets listed within kot.toml".to_owned()
+        );
+      }
+      targets.push((source.components().collect(), expand_target(target, install_dir)));
+    }
+    return Ok(targets);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Parses a kot.toml that lists packages and their dependencies
+  fn get_repo(contents: &str) -> Repo {
+    return toml::from_str(contents).unwrap();
+  }
+
+  /// Returns the message of the ConfigError returned while resolving the dependencies of packages
+  fn get_error(repo: &Repo, packages: &[&str]) -> String {
+    let packages: Vec<String> = packages.iter().map(|name| name.to_string()).collect();
+    return match repo.resolve_depends(packages.iter()).unwrap_err().kind {
+      ErrorKind::ConfigError(message) => message,
+      kind => panic!("Unexpected error {:?}", kind),
+    };
+  }
+
+  #[test]
+  fn depends_are_ordered() {
+    let repo = get_repo(r#"
+      [packages.bash]
+      [packages.git]
+      depends = ["bash"]
+      [packages.vim]
+      depends = ["git", "bash"]
+      [packages.nvim]
+      depends = ["vim"]
+    "#);
+    let vim = ["vim".to_owned()];
+    assert_eq!(repo.resolve_depends(vim.iter()).unwrap(), ["bash", "git", "vim"]);
+    assert_eq!(repo.resolve_depends(repo.packages.keys()).unwrap(), ["bash", "git", "vim", "nvim"]);
+    let packages: Vec<_> = repo.get_packages(Path::new("/dot"), &["nvim".to_owned(), "bash".to_owned()]).unwrap()
+        .into_iter().map(|package| (package.name.unwrap(), package.dir)).collect();
+    assert_eq!(packages, [
+      ("bash".to_owned(), PathBuf::from("/dot/bash")),
+      ("git".to_owned(), PathBuf::from("/dot/git")),
+      ("vim".to_owned(), PathBuf::from("/dot/vim")),
+      ("nvim".to_owned(), PathBuf::from("/dot/nvim")),
+    ]);
+    assert_eq!(repo.get_dependents(&"git".to_owned()), ["nvim", "vim"]);
+    assert!(repo.get_dependents(&"nvim".to_owned()).is_empty());
+  }
+
+  #[test]
+  fn depends_cycles_are_errors() {
+    let repo = get_repo(r#"
+      [packages.bash]
+      depends = ["vim"]
+      [packages.git]
+      depends = ["bash"]
+      [packages.vim]
+      depends = ["git"]
+      [packages.zsh]
+      depends = ["zsh"]
+      [packages.tmux]
+      depends = ["screen"]
+    "#);
+    assert_eq!(get_error(&repo, &["vim"]), "Package dependencies form a cycle: vim -> git -> bash -> vim");
+    assert_eq!(get_error(&repo, &["zsh"]), "Package dependencies form a cycle: zsh -> zsh");
+    assert_eq!(get_error(&repo, &["tmux"]), "Package \"tmux\" depends on \"screen\", which is not listed");
+    assert!(repo.get_dependents(&"bash".to_owned()).is_empty());
+  }
+}
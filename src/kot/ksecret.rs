@@ -0,0 +1,177 @@
+/*##############################################################################
+## Author: Shaun Reed                                                         ##
+## Legal: All Content (c) 2021 Shaun Reed, all rights reserved                ##
+## About: Encrypted secrets stored within dotfiles                            ##
+##                                                                            ##
+## Contact: shaunrd0@gmail.com  | URL: www.shaunreed.com | GitHub: shaunrd0   ##
+##############################################################################*/
+
+use std::ffi::{OsStr, OsString};
+use std::io::{Read, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use age::secrecy::SecretString;
+
+use crate::kot::err;
+use crate::kot::kerror::{Error, ErrorKind};
+use super::kfs;
+use super::kio;
+
+// =============================================================================
+// STRUCTS
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+/// Suffix of files within the dotfiles that are encrypted, and decrypted before they are installed
+/// + .netrc.age is decrypted and installed as .netrc
+pub const SECRET_SUFFIX: &str = ".age";
+
+/// Environment variable that provides the passphrase used to encrypt and decrypt secrets
+/// + If it is not set, the passphrase is prompted for once each time kot is run
+pub const PASSPHRASE_ENV: &str = "KOT_PASSPHRASE";
+
+/// Passphrase provided for this run, so each secret does not prompt for it again
+static PASSPHRASE: OnceLock<String> = OnceLock::new();
+
+// =============================================================================
+// IMPLEMENTATION
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+/// Checks if a file within the dotfiles is an encrypted secret
+/// + Directories are never secrets, even if their name ends with SECRET_SUFFIX
+pub fn is_secret(path: &Path) -> bool {
+  return path.is_file() && path.file_name().and_then(|name| name.to_str())
+      .is_some_and(|name| name.len() > SECRET_SUFFIX.len() && name.ends_with(SECRET_SUFFIX));
+}
+
+/// Returns the name a secret is installed as, without SECRET_SUFFIX
+pub fn get_target_name(name: &OsStr) -> OsString {
+  return match name.to_str() {
+    Some(name) if name.ends_with(SECRET_SUFFIX) => {
+      OsString::from(&name[..name.len() - SECRET_SUFFIX.len()])
+    },
+    _ => name.to_owned(),
+  };
+}
+
+/// Constructs a new PathBuf pointing to the directory secrets are decrypted into
+/// + Each installation decrypts into a subdirectory named <DOTFILES_NAME>[.<PACKAGE>]
+/// + Directories within it are only accessible by the current user
+pub fn get_secrets_root() -> super::Result<PathBuf> {
+  let mut secrets_root = kfs::get_data_dir()?;
+  secrets_root.push("secrets/");
+  return Ok(secrets_root);
+}
+
+/// Returns the path a secret within dotfiles is decrypted to within secrets_dir
+/// + Decrypted files mirror the layout of the secrets within the dotfiles
+pub fn get_decrypted_path(secrets_dir: &Path, dotfiles: &Path, secret: &Path) -> PathBuf {
+  let relative = secret.strip_prefix(dotfiles).unwrap_or(secret);
+  let mut decrypted_path = secrets_dir.join(relative);
+  decrypted_path.set_file_name(get_target_name(secret.file_name().unwrap()));
+  return decrypted_path;
+}
+
+/// Returns the passphrase used to encrypt and decrypt secrets
+/// + Read from PASSPHRASE_ENV if it is set, otherwise the user is prompted once for each run
+/// + If confirm is set, the user must enter the passphrase twice
+fn get_passphrase(confirm: bool) -> super::Result<SecretString> {
+  if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+    return Ok(SecretString::from(passphrase));
+  }
+  if let Some(passphrase) = PASSPHRASE.get() {
+    return Ok(SecretString::from(passphrase.as_str()));
+  }
+  let passphrase = kio::prompt_passphrase("Passphrase for secrets: ".to_owned())?;
+  if confirm && kio::prompt_passphrase("Confirm passphrase: ".to_owned())? != passphrase {
+    err!(
+      ErrorKind::SecretError("Passphrases do not match".to_owned()),
+      "Enter the same passphrase twice".to_owned()
+    );
+  }
+  return Ok(SecretString::from(PASSPHRASE.get_or_init(|| passphrase).as_str()));
+}
+
+/// Decrypts a secret within the dotfiles and returns its contents
+pub fn decrypt(secret: &PathBuf) -> super::Result<Vec<u8>> {
+  let ciphertext = std::fs::read(secret)?;
+  let decryptor = age::Decryptor::new(&ciphertext[..])?;
+  if !decryptor.is_scrypt() {
+    err!(
+      ErrorKind::SecretError(format!("{:?} is not encrypted with a passphrase", secret)),
+      "Encrypt secrets using kot encrypt".to_owned()
+    );
+  }
+  let identity = age::scrypt::Identity::new(get_passphrase(false)?);
+  let mut reader = match decryptor.decrypt(std::iter::once(&identity as &dyn age::Identity)) {
+    Ok(reader) => reader,
+    Err(e) => err!(
+      ErrorKind::SecretError(e.to_string()),
+      format!("Unable to decrypt {:?}; Check the passphrase for secrets", secret)
+    ),
+  };
+  let mut contents = vec![];
+  reader.read_to_end(&mut contents)?;
+  return Ok(contents);
+}
+
+/// Encrypts file with a passphrase, and writes the secret to output
+pub fn encrypt(file: &PathBuf, output: &PathBuf) -> super::Result<()> {
+  let contents = std::fs::read(file)?;
+  let encryptor = age::Encryptor::with_user_passphrase(get_passphrase(true)?);
+  let mut ciphertext = vec![];
+  let mut writer = encryptor.wrap_output(&mut ciphertext)?;
+  writer.write_all(&contents)?;
+  writer.finish()?;
+  std::fs::write(output, ciphertext)?;
+  return Ok(());
+}
+
+/// Writes the decrypted contents of a secret to decrypted_path
+/// + The file is only readable and writable by the current user, and is written to a temporary file first
+pub fn write_decrypted(decrypted_path: &Path, contents: &[u8]) -> super::Result<()> {
+  std::fs::DirBuilder::new().recursive(true).mode(0o700)
+      .create(decrypted_path.parent().unwrap())?;
+  let temp_path = kfs::get_temp_path(decrypted_path);
+  if temp_path.symlink_metadata().is_ok() {
+    std::fs::remove_file(&temp_path)?;
+  }
+  let mut file = std::fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(&temp_path)?;
+  file.write_all(contents)?;
+  std::fs::rename(&temp_path, decrypted_path)?;
+  return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::os::unix::fs::PermissionsExt;
+
+  #[test]
+  fn encrypted_secrets_round_trip() {
+    let dir = kfs::get_test_dir("secrets");
+    let (file, secret) = (dir.join("netrc"), dir.join("dot/.netrc.age"));
+    kfs::write(&file, "machine example.com login kapper password hunter2\n");
+    kfs::create_dir_all(&dir.join("dot")).unwrap();
+    PASSPHRASE.get_or_init(|| "correct horse battery staple".to_owned());
+    encrypt(&file, &secret).unwrap();
+    assert!(is_secret(&secret));
+    assert_ne!(std::fs::read(&secret).unwrap(), std::fs::read(&file).unwrap());
+    let contents = decrypt(&secret).unwrap();
+    assert_eq!(contents, std::fs::read(&file).unwrap());
+
+    // Decrypted secrets are only accessible by the current user
+    let decrypted_path = get_decrypted_path(&dir.join("secrets/dot"), &dir.join("dot"), &secret);
+    assert_eq!(decrypted_path, dir.join("secrets/dot/.netrc"));
+    write_decrypted(&decrypted_path, &contents).unwrap();
+    assert_eq!(std::fs::read(&decrypted_path).unwrap(), contents);
+    let mode = |path: &Path| path.metadata().unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode(&decrypted_path), 0o600);
+    assert_eq!(mode(&dir.join("secrets/dot")), 0o700);
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
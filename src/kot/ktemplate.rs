@@ -0,0 +1,160 @@
+/*##############################################################################
+## Author: Shaun Reed                                                         ##
+## Legal: All Content (c) 2021 Shaun Reed, all rights reserved                ##
+## About: Rendering of configuration templates within dotfiles                ##
+##                                                                            ##
+## Contact: shaunrd0@gmail.com  | URL: www.shaunreed.com | GitHub: shaunrd0   ##
+##############################################################################*/
+
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use regex::Regex;
+
+use crate::kot::err;
+use crate::kot::kerror::{Error, ErrorKind};
+use super::kfs;
+
+// =============================================================================
+// STRUCTS
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+/// Suffix of files within the dotfiles that are rendered before they are installed
+/// + .gitconfig.tmpl is rendered and installed as .gitconfig
+pub const TEMPLATE_SUFFIX: &str = ".tmpl";
+
+/// Pattern matching each {{ name }} within a template, compiled once for each run
+static PATTERN: OnceLock<Regex> = OnceLock::new();
+
+// =============================================================================
+// IMPLEMENTATION
+// =============================================================================
+
+// -----------------------------------------------------------------------------
+
+/// Checks if a file within the dotfiles is a template
+/// + Directories are never templates, even if their name ends with TEMPLATE_SUFFIX
+pub fn is_template(path: &Path) -> bool {
+  return path.is_file() && path.file_name().and_then(|name| name.to_str())
+      .is_some_and(|name| name.len() > TEMPLATE_SUFFIX.len() && name.ends_with(TEMPLATE_SUFFIX));
+}
+
+/// Returns the name a template is installed as, without TEMPLATE_SUFFIX
+pub fn get_target_name(name: &OsStr) -> OsString {
+  return match name.to_str() {
+    Some(name) if name.ends_with(TEMPLATE_SUFFIX) => {
+      OsString::from(&name[..name.len() - TEMPLATE_SUFFIX.len()])
+    },
+    _ => name.to_owned(),
+  };
+}
+
+/// Constructs a new PathBuf pointing to the directory templates are rendered into
+/// + Each installation renders into a subdirectory named <DOTFILES_NAME>[.<PACKAGE>]
+pub fn get_rendered_root() -> super::Result<PathBuf> {
+  let mut rendered_root = kfs::get_data_dir()?;
+  rendered_root.push("rendered/");
+  return Ok(rendered_root);
+}
+
+/// Returns the path a template within dotfiles is rendered to within rendered_dir
+/// + Rendered files mirror the layout of the templates within the dotfiles
+pub fn get_rendered_path(rendered_dir: &Path, dotfiles: &Path, template: &Path) -> PathBuf {
+  let relative = template.strip_prefix(dotfiles).unwrap_or(template);
+  let mut rendered_path = rendered_dir.join(relative);
+  rendered_path.set_file_name(get_target_name(template.file_name().unwrap()));
+  return rendered_path;
+}
+
+/// Returns the variables available to templates
+/// + hostname, user, os, and profile are provided by kot; profile is the last profile installed, if any
+/// + Variables provided by configuration files take precedence over the variables provided by kot
+/// + If USER is not set, user must be provided by a configuration file
+pub fn get_vars(profiles: &[String], config_vars: &HashMap<String, String>)
+                -> super::Result<HashMap<String, String>> {
+  let mut vars = HashMap::new();
+  vars.insert("hostname".to_owned(), kfs::get_hostname().unwrap_or_default());
+  if !config_vars.contains_key("user") {
+    let user = match std::env::var("USER") {
+      Ok(user) if !user.is_empty() => user,
+      _ => err!(
+        ErrorKind::ConfigError("Unable to find the current user; USER is not set".to_owned()),
+        "Set USER, or provide user within [vars] of a kot configuration file".to_owned()
+      ),
+    };
+    vars.insert("user".to_owned(), user);
+  }
+  vars.insert("os".to_owned(), std::env::consts::OS.to_owned());
+  vars.insert("profile".to_owned(), profiles.last().cloned().unwrap_or_default());
+  vars.extend(config_vars.to_owned());
+  return Ok(vars);
+}
+
+/// Renders a template, replacing each {{ name }} with the value of the variable
+/// + Unknown variables are an error, so a template is never installed partially rendered
+pub fn render(template: &PathBuf, vars: &HashMap<String, String>) -> super::Result<String> {
+  let contents = std::fs::read_to_string(template)?;
+  let pattern = PATTERN.get_or_init(|| Regex::new(r"\{\{\s*([A-Za-z0-9_.-]+)\s*\}\}").unwrap());
+  if let Some(unknown) = pattern.captures_iter(&contents)
+      .find(|captures| !vars.contains_key(&captures[1])) {
+    err!(
+      ErrorKind::ConfigError(format!("Unknown variable {:?} within {:?}", &unknown[1], template)),
+      "Provide the variable within [vars] of a kot configuration file".to_owned()
+    );
+  }
+  return Ok(pattern.replace_all(&contents, |captures: &regex::Captures| {
+    vars[&captures[1]].to_owned()
+  }).into_owned());
+}
+
+/// Writes the rendered contents of template to rendered_path
+/// + The rendered file is written to a temporary file first and then renamed, and uses the permissions of template
+pub fn write_rendered(template: &Path, rendered_path: &Path, contents: &str) -> super::Result<()> {
+  kfs::create_dir_all(&rendered_path.parent().unwrap().to_path_buf())?;
+  let temp_path = kfs::get_temp_path(rendered_path);
+  std::fs::write(&temp_path, contents)?;
+  std::fs::set_permissions(&temp_path, template.metadata()?.permissions())?;
+  std::fs::rename(&temp_path, rendered_path)?;
+  return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use kfs::write;
+
+  #[test]
+  fn renders_templates() {
+    let dir = kfs::get_test_dir("renders-templates");
+    let template = dir.join(".gitconfig.tmpl");
+    write(&template, "[user]\n  name = {{ user }}\n  email = {{email}}\n");
+    assert!(is_template(&template));
+    assert_eq!(get_target_name(template.file_name().unwrap()), ".gitconfig");
+
+    let config_vars = HashMap::from([
+      ("user".to_owned(), "kapper".to_owned()),
+      ("email".to_owned(), "kapper@example.com".to_owned()),
+    ]);
+    let vars = get_vars(&["laptop".to_owned()], &config_vars).unwrap();
+    assert_eq!(vars["profile"], "laptop");
+    assert_eq!(vars["os"], std::env::consts::OS);
+    assert_eq!(render(&template, &vars).unwrap(),
+               "[user]\n  name = kapper\n  email = kapper@example.com\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn unknown_variables_are_errors() {
+    let dir = kfs::get_test_dir("unknown-variables");
+    let template = dir.join(".bashrc.tmpl");
+    write(&template, "export EDITOR={{ editor }}\n");
+    let vars = HashMap::from([("user".to_owned(), "kapper".to_owned())]);
+    let error = render(&template, &vars).unwrap_err();
+    assert!(matches!(&error.kind, ErrorKind::ConfigError(message) if message.contains("\"editor\"")),
+            "{}", error);
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
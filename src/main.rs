@@ -6,8 +6,6 @@
 ## Contact: shaunrd0@gmail.com  | URL: www.shaunreed.com | GitHub: shaunrd0   ##
 ##############################################################################*/
 
-use crate::kot::kerror::ErrorKind;
-
 mod kot;
 
 // =============================================================================
@@ -19,8 +17,6 @@ mod kot;
 fn main() -> kot::Result<()> {
     // Call augmented kot::cli::from_args() to parse CLI arguments
     let mut args = kot::kcli::from_args()?;
-    // At this point all paths exist and have been converted to absolute paths
-    println!("args: {:?}\n", args);
 
     // Apply CLI arguments and attempt to install dotfiles
     return kot::handle_args(&mut args);